@@ -1,7 +1,37 @@
-use std::time::Duration;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use vmregex::Regex;
+use vmregex::{Regex, RegexBuilder};
+
+/// Allocator that counts the allocations, to compare how often the matching methods allocate.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Number of allocations made by `f`.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
 
 pub fn benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("a?^na^n benchmark");
@@ -15,13 +45,293 @@ pub fn benchmark(c: &mut Criterion) {
             &input,
             |b, (pattern, text)| {
                 b.iter(|| {
-                    let re = Regex::new(&pattern).unwrap();
-                    re.is_match(&text).unwrap();
+                    let re = Regex::new(pattern).unwrap();
+                    re.is_prefix_match(text).unwrap();
                 })
             },
         );
     }
 }
 
-criterion_group!(benches, benchmark);
+pub fn suffix_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("suffix literal benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = RegexBuilder::new(".*txt")
+        .reverse_program(true)
+        .build()
+        .unwrap();
+    let inputs = [100, 1000].map(|n| (n, "x".repeat(n)));
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("forward", n), &text, |b, text| {
+            b.iter(|| {
+                assert!(!re.is_match(text).unwrap());
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("reverse", n), &text, |b, text| {
+            b.iter(|| assert!(!re.is_suffix_match(text).unwrap()))
+        });
+    }
+}
+
+pub fn literal_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("literal benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = Regex::new("needle").unwrap();
+    let inputs = [100, 1000].map(|n| (n, "haystack ".repeat(n) + "needle"));
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("find", n), &text, |b, text| {
+            b.iter(|| re.find(text).unwrap().unwrap())
+        });
+    }
+}
+
+pub fn alternation_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("alternation benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let words = [
+        "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india",
+        "juliett",
+    ];
+    let re = Regex::new(&format!("({})", words.join("|"))).unwrap();
+    let inputs = [100, 1000].map(|n| (n, "kilo lima mike ".repeat(n) + "juliett"));
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("find", n), &text, |b, text| {
+            b.iter(|| re.find(text).unwrap().unwrap())
+        });
+    }
+}
+
+pub fn class_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("class benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = Regex::new("[a-zA-Z0-9]+_").unwrap();
+    let inputs = [100, 1000].map(|n| (n, "abcXYZ0123".repeat(n / 10) + "_"));
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("is_match", n), &text, |b, text| {
+            b.iter(|| assert!(re.is_match(text).unwrap()))
+        });
+    }
+}
+
+pub fn ascii_class_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ascii class benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let pattern = "[a-zA-Z0-9_]+";
+    let bitmap = Regex::new(pattern).unwrap();
+    // A custom `char_eq` keeps the class as a split for each operand.
+    let splits = RegexBuilder::new(pattern)
+        .char_eq(|c, cc| c == cc)
+        .build()
+        .unwrap();
+    let inputs = [100, 1000].map(|n| (n, "snake_case_identifier_42 ".repeat(n)));
+
+    for (n, text) in inputs {
+        for (name, re) in [("bitmap", &bitmap), ("splits", &splits)] {
+            group.bench_with_input(BenchmarkId::new(name, n), &text, |b, text| {
+                b.iter(|| re.find_iter(text).count())
+            });
+        }
+    }
+}
+
+pub fn required_chars_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("required chars benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let pattern = "(a|b)+z";
+    let filtered = Regex::new(pattern).unwrap();
+    // A custom `char_eq` disables the filter, so the text is searched from every offset.
+    let searched = RegexBuilder::new(pattern)
+        .char_eq(|c, cc| c == cc)
+        .build()
+        .unwrap();
+    let inputs = [100, 1000].map(|n| (n, "ab".repeat(n)));
+
+    for (n, text) in inputs {
+        for (name, re) in [("filtered", &filtered), ("searched", &searched)] {
+            group.bench_with_input(BenchmarkId::new(name, n), &text, |b, text| {
+                b.iter(|| assert!(!re.is_match(text).unwrap()))
+            });
+        }
+    }
+}
+
+pub fn nested_quantifier_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nested quantifier benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = Regex::new("(a+)+b").unwrap();
+    let inputs = [4, 8, 12].map(|n| (n, "a".repeat(n)));
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("is_match", n), &text, |b, text| {
+            b.iter(|| assert!(!re.is_prefix_match(text).unwrap()))
+        });
+    }
+}
+
+pub fn find_iter_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_iter benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    // Words, as `\w+` would match them.
+    let re = Regex::new("[a-zA-Z0-9_]+").unwrap();
+    let inputs = [1000, 10000].map(|n| {
+        (
+            n,
+            "The quick brown fox, jumps over_the lazy dog 42 times. ".repeat(n / 10),
+        )
+    });
+
+    for (n, text) in inputs {
+        group.bench_with_input(BenchmarkId::new("words", n), &text, |b, text| {
+            b.iter(|| assert_eq!(re.find_iter(text).count(), n))
+        });
+    }
+}
+
+pub fn reserve_capacity_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reserve capacity benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    // Every `a?` leaves a thread behind, so the stack grows as long as the program.
+    let n = 500;
+    let pattern = "a?".repeat(n);
+    let text = "a".repeat(n);
+    let reserved = RegexBuilder::new(&pattern)
+        .reserve_capacity(true)
+        .build()
+        .unwrap();
+    let unreserved = Regex::new(&pattern).unwrap();
+
+    group.bench_with_input(BenchmarkId::new("reserved", n), &text, |b, text| {
+        b.iter(|| assert!(reserved.is_prefix_match(text).unwrap()))
+    });
+    group.bench_with_input(BenchmarkId::new("unreserved", n), &text, |b, text| {
+        b.iter(|| assert!(unreserved.is_prefix_match(text).unwrap()))
+    });
+}
+
+pub fn matcher_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matcher benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = Regex::new("(a|b)*c").unwrap();
+    let lines = (0..1000)
+        .map(|i| "ab".repeat(i % 20) + if i % 2 == 0 { "c" } else { "d" })
+        .collect::<Vec<_>>();
+
+    let mut matcher = re.matcher();
+    // Grow the buffers of the matcher, so that only the steady state is counted.
+    lines.iter().for_each(|line| {
+        matcher.is_match(line).unwrap();
+    });
+    let regex_allocations = count_allocations(|| {
+        lines.iter().for_each(|line| {
+            re.is_match(line).unwrap();
+        })
+    });
+    let matcher_allocations = count_allocations(|| {
+        lines.iter().for_each(|line| {
+            matcher.is_match(line).unwrap();
+        })
+    });
+    println!(
+        "allocations for {} lines: Regex::is_match {regex_allocations}, Matcher::is_match {matcher_allocations}",
+        lines.len()
+    );
+
+    group.bench_with_input(
+        BenchmarkId::new("regex", lines.len()),
+        &lines,
+        |b, lines| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .filter(|line| re.is_match(line).unwrap())
+                    .count()
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("matcher", lines.len()),
+        &lines,
+        |b, lines| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .filter(|line| matcher.is_match(line).unwrap())
+                    .count()
+            })
+        },
+    );
+}
+
+pub fn log_scan_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("log scan benchmark");
+    group.measurement_time(Duration::from_secs(1));
+
+    let re = Regex::new("ERROR.*(timeout|refused)").unwrap();
+    // One line in a hundred is an error, and the others still have every char of `ERROR`, so only the required
+    // literal rejects them without a search.
+    let lines = (0..10_000)
+        .map(|i| {
+            let level = if i % 100 == 0 { "ERROR" } else { "INFO" };
+            format!(
+                "00:00:{:02} {level} ROUTER request {i}: timeout after {i} ms",
+                i % 60
+            )
+        })
+        .collect::<Vec<_>>();
+
+    group.bench_with_input(
+        BenchmarkId::new("is_match", lines.len()),
+        &lines,
+        |b, lines| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .filter(|line| re.is_match(line).unwrap())
+                    .count()
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("any_match_in", lines.len()),
+        &lines,
+        |b, lines| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .filter(|line| re.any_match_in(line).unwrap())
+                    .count()
+            })
+        },
+    );
+}
+
+criterion_group!(
+    benches,
+    benchmark,
+    suffix_benchmark,
+    literal_benchmark,
+    alternation_benchmark,
+    class_benchmark,
+    ascii_class_benchmark,
+    required_chars_benchmark,
+    nested_quantifier_benchmark,
+    find_iter_benchmark,
+    reserve_capacity_benchmark,
+    matcher_benchmark,
+    log_scan_benchmark
+);
 criterion_main!(benches);
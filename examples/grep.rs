@@ -4,32 +4,114 @@ use std::{
 };
 
 use anyhow::Result;
-use clap::Parser;
-use vmregex::Regex;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use vmregex::{Match, Matcher, Regex};
 
 #[derive(Parser)]
 struct Cli {
-    pattern: String,
-    file: String,
+    /// Pattern to search for. It can be given more than once, and a line is printed if any of them matches.
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERN")]
+    regexps: Vec<String>,
+    /// Print only the matched parts of the lines, each on its own line.
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+    /// Print only the lines that a pattern matches as a whole.
+    #[arg(short = 'x', long)]
+    line_regexp: bool,
+    /// Pattern, unless given with `-e`, and file to search.
+    #[arg(required = true, num_args = 1..=2, value_name = "[PATTERN] FILE")]
+    args: Vec<String>,
 }
 
 fn main() -> Result<()> {
-    let args = Cli::parse();
+    let Cli {
+        regexps,
+        only_matching,
+        line_regexp,
+        args,
+    } = Cli::parse();
+    let (patterns, file) = match (regexps.is_empty(), &args[..]) {
+        (true, [pattern, file]) => (vec![pattern.clone()], file),
+        (false, [file]) => (regexps, file),
+        _ => Cli::command()
+            .error(
+                ErrorKind::WrongNumberOfValues,
+                "expected a pattern and a file, or a file after -e patterns",
+            )
+            .exit(),
+    };
 
-    let file = File::open(args.file)?;
+    let file = File::open(file)?;
     let reader = BufReader::new(file);
-    let re = Regex::new(&args.pattern)?;
+    let res = patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
 
+    let mut matchers = res.iter().map(Regex::matcher).collect::<Vec<_>>();
     for line in reader.lines() {
         let line = line?;
-        let indices = line.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
-        for i in indices {
-            if re.is_match(&line[i..])? {
+        if line_regexp {
+            // The whole line is the match, so it is printed with `-o` too, unless it is empty.
+            if is_line_match(&res, &line)? && !(only_matching && line.is_empty()) {
                 println!("{line}");
-                break;
             }
+        } else if only_matching {
+            print_matches(&mut matchers, &line)?;
+        } else if is_match(&res, &line)? {
+            println!("{line}");
         }
     }
 
     Ok(())
 }
+
+/// Print the non-empty parts of the line that any of the regular expressions matches, leftmost first, taking the
+/// longest of the matches that start at the same position.
+fn print_matches(matchers: &mut [Matcher], line: &str) -> Result<()> {
+    let mut start = 0;
+    loop {
+        let mut leftmost: Option<Match> = None;
+        for matcher in matchers.iter_mut() {
+            if let Some(m) = matcher.find_at(line, start)? {
+                // The longest match wins a tie, so that the position is only skipped if every match there is empty.
+                let better = |l: &Match| {
+                    m.start() < l.start() || m.start() == l.start() && m.end() > l.end()
+                };
+                if leftmost.as_ref().map_or(true, better) {
+                    leftmost = Some(m);
+                }
+            }
+        }
+        let Some(m) = leftmost else {
+            return Ok(());
+        };
+
+        if m.as_str().is_empty() {
+            start = m.end() + 1;
+        } else {
+            println!("{}", m.as_str());
+            start = m.end();
+        }
+    }
+}
+
+/// Check if any of the regular expressions matches somewhere in the line.
+fn is_match(res: &[Regex], line: &str) -> Result<bool> {
+    for re in res {
+        if re.is_match(line)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Check if any of the regular expressions matches the whole line.
+fn is_line_match(res: &[Regex], line: &str) -> Result<bool> {
+    for re in res {
+        if re.full_line_match(line)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -1,13 +1,14 @@
-use crate::parser::Ast;
+use alloc::{vec, vec::Vec};
+use core::fmt;
 
-use thiserror::Error;
+use crate::parser::Ast;
 
-/// Instruction set for the virtual machine.
+/// Program counter, i.e. the index of an instruction in the program.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Pc(pub usize);
 
 impl Pc {
-    pub fn inc<E>(&mut self, err: impl Fn() -> E) -> Result<Self, E> {
+    pub(crate) fn inc<E>(&mut self, err: impl Fn() -> E) -> Result<Self, E> {
         if let Some(new) = self.0.checked_add(1) {
             self.0 = new;
             Ok(*self)
@@ -17,26 +18,98 @@ impl Pc {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Instruction set for the virtual machine.
+///
+/// A thread of the machine starts at PC 0 with the string pointer at the start of the match, and runs until it
+/// succeeds or fails. Unless stated otherwise, an instruction continues at the next PC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
+    /// Consume the char at the string pointer if it equals the given one, and fail otherwise.
     Char(char),
+    /// Succeed, with the match ending at the string pointer.
     Match,
+    /// Continue at the given PC.
     Jmp(Pc),
+    /// Continue at the first PC, and if that thread fails, backtrack to the second one with the same string pointer.
     Split(Pc, Pc),
+    /// Consume the char at the string pointer if it is within the given inclusive range, and fail otherwise.
+    Range(char, char),
+    /// Consume the char at the string pointer if it is ASCII and in the bitmap, where bit `c % 64` of word `c / 64`
+    /// stands for the char `c`, and fail otherwise.
+    AsciiClass([u64; 2]),
+    /// Consume any char, and fail at the end of the text.
     AnyByte,
+    /// Consume any char but `\n`, and fail at the end of the text.
+    AnyByteExceptNewline,
+    /// Succeed only at the position where the search began, without consuming a char.
+    SearchStart,
+    /// Run the program from the next PC at the string pointer as a separate search, which succeeds at its own
+    /// `Match`, and continue at the given PC without consuming a char if it succeeds. Fail otherwise.
+    LookAhead(Pc),
+    /// Like `LookAhead`, but continue if the separate search fails, and fail if it succeeds.
+    NegativeLookAhead(Pc),
+    /// Like `LookAhead`, but run the separate search from the given number of chars before the string pointer, and
+    /// succeed only if its `Match` is reached at the string pointer.
+    LookBehind(Pc, usize),
+    /// Like `LookBehind`, but continue if the separate search fails, and fail if it succeeds.
+    NegativeLookBehind(Pc, usize),
+    /// Start the loop with the given number, by recording the string pointer as the start of its first iteration, or
+    /// no string pointer if the flag is set, so that the first iteration may consume nothing as with `+`.
+    LoopStart(usize, bool),
+    /// End an iteration of the loop with the given number: fail if it consumed no char, so that the loop cannot go
+    /// on without progress, and record the string pointer as the start of the next iteration otherwise.
+    LoopCheck(usize),
+    /// Never succeed, so the machine backtracks to the next alternative. Patterns do not generate it yet, but it
+    /// marks a dead end explicitly, e.g. for a branch removed by an optimization.
+    Fail,
 }
 
-#[derive(Error, Debug)]
+#[derive(Debug)]
 pub enum GenerateCodeError {
-    #[error("program counter overflow occured")]
     PcOverflow,
+    /// The AST has a node that the machine cannot run, which only a hand-built AST can have.
+    UnsupportedConstruct {
+        construct: &'static str,
+    },
+    /// A label could not be backpatched, because the instruction at its PC is not the one generated there. It means
+    /// a bug in the code generator rather than in the pattern.
+    Internal {
+        expected: &'static str,
+        pc: usize,
+    },
+}
+
+impl fmt::Display for GenerateCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PcOverflow => write!(f, "program counter overflow occured"),
+            Self::UnsupportedConstruct { construct } => {
+                write!(f, "unsupported construct: {construct}")
+            }
+            Self::Internal { expected, pc } => write!(
+                f,
+                "internal error: expected an Instruction::{expected} at PC {pc}, but found a different instruction"
+            ),
+        }
+    }
 }
 
+impl core::error::Error for GenerateCodeError {}
+
 #[derive(Debug, Default)]
 struct CodeGenerator {
     // pc always points to the next instruction generated. In other words, it is always `instructions.len() == pc`.
     pc: Pc,
     instructions: Vec<Instruction>,
+    // Whether `.` does not match `\n`, i.e. generates `AnyByteExceptNewline` rather than `AnyByte`.
+    dot_except_newline: bool,
+    // Whether an alternation of ASCII chars and ranges generates `AsciiClass` rather than splits.
+    ascii_classes: bool,
+    // Loops generated with `LoopStart`, which numbers them.
+    loops: usize,
+    // Instruction that the next char instruction overwrites, to test a corrupted program.
+    #[cfg(test)]
+    corrupt: Option<(Pc, Instruction)>,
 }
 
 impl CodeGenerator {
@@ -53,21 +126,33 @@ impl CodeGenerator {
 
     fn expr(&mut self, ast: Ast) -> Result<(), GenerateCodeError> {
         match ast {
-            Ast::Char(c) => self.char(c)?,
-            Ast::Concat(concat) => self.concat(concat)?,
-            Ast::Or(lhs, rhs) => self.or(*lhs, *rhs)?,
-            Ast::Question(e) => self.question(*e)?,
-            Ast::Star(e) => self.star(*e)?,
-            Ast::Plus(e) => self.plus(*e)?,
-            Ast::Dot => self.dot()?,
-        };
-        Ok(())
+            Ast::Char(c) => self.char(c),
+            Ast::Concat(concat) => self.concat(concat),
+            alternation @ Ast::Or(..) => self.or(alternation.into_or_operands()),
+            Ast::Question(e) => self.question(*e),
+            Ast::Star(e) => self.star(*e),
+            Ast::Plus(e) => self.plus(*e),
+            Ast::Dot => self.dot(self.dot_except_newline),
+            Ast::ScopedDot(newline) => self.dot(!newline),
+            Ast::Range(lo, hi) => self.range(lo, hi),
+            Ast::SearchStart => self.search_start(),
+            // The empty string is matched without any instruction.
+            Ast::Empty => Ok(()),
+            Ast::LookAhead(e) => self.look_ahead(*e, false),
+            Ast::NegativeLookAhead(e) => self.look_ahead(*e, true),
+            Ast::LookBehind(e, width) => self.look_behind(*e, width, false),
+            Ast::NegativeLookBehind(e, width) => self.look_behind(*e, width, true),
+        }
     }
 
     /// Generate char instruction.
     fn char(&mut self, c: char) -> Result<(), GenerateCodeError> {
         self.instructions.push(Instruction::Char(c));
         self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        #[cfg(test)]
+        if let Some((pc, instruction)) = self.corrupt.take() {
+            self.instructions[pc.0] = instruction;
+        }
         Ok(())
     }
 
@@ -95,43 +180,58 @@ impl CodeGenerator {
     /// L2: e2 code
     /// L3:
     /// ```
-    fn or(&mut self, lhs: Ast, rhs: Ast) -> Result<(), GenerateCodeError> {
+    ///
+    /// The operands of a chain e1|e2|...|en are generated in a loop instead of recursing into each `Or`, so that a long
+    /// alternation does not overflow the stack. The code is the same as the nested one, unless the operands are an
+    /// ASCII class, which is tested at once with `AsciiClass` if `ascii_classes` is set.
+    fn or(&mut self, mut operands: Vec<Ast>) -> Result<(), GenerateCodeError> {
         assert_eq!(self.instructions.len(), self.pc.0);
 
-        let split_pc = self.pc;
-        // split L1, L2
-        let l1 = self.pc.inc(|| GenerateCodeError::PcOverflow)?;
-        self.instructions.push(Instruction::Split(l1, Pc(0))); // L2 TBD.
-        assert_eq!(self.instructions.len(), self.pc.0);
+        if let Some(bits) = ascii_class(&operands).filter(|_| self.ascii_classes) {
+            return self.ascii_class(bits);
+        }
 
-        // e1
-        self.expr(lhs)?;
-        // jmp L3
-        let jmp_pc = self.pc;
-        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
-        self.instructions.push(Instruction::Jmp(Pc(0))); // L3 TBD.
-        assert_eq!(self.instructions.len(), self.pc.0);
+        // en is generated after the loop, without a split.
+        let last = operands.pop().expect("an alternation has operands");
+        let mut jmp_pcs = Vec::new();
+        for operand in operands {
+            let split_pc = self.pc;
+            // split L1, L2
+            let l1 = self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+            self.instructions.push(Instruction::Split(l1, Pc(0))); // L2 TBD.
+            assert_eq!(self.instructions.len(), self.pc.0);
 
-        if let Some(Instruction::Split(_, l2)) = self.instructions.get_mut(split_pc.0) {
-            *l2 = self.pc;
-        } else {
-            unreachable!(
-                "Expected an Instruction::Split at PC {}, but found a different instruction",
-                split_pc.0
-            );
+            // e1
+            self.expr(operand)?;
+            // jmp L3
+            jmp_pcs.push(self.pc);
+            self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+            self.instructions.push(Instruction::Jmp(Pc(0))); // L3 TBD.
+            assert_eq!(self.instructions.len(), self.pc.0);
+
+            if let Some(Instruction::Split(_, l2)) = self.instructions.get_mut(split_pc.0) {
+                *l2 = self.pc;
+            } else {
+                return Err(GenerateCodeError::Internal {
+                    expected: "Split",
+                    pc: split_pc.0,
+                });
+            }
         }
 
         // e2
-        self.expr(rhs)?;
+        self.expr(last)?;
         assert_eq!(self.instructions.len(), self.pc.0);
 
-        if let Some(Instruction::Jmp(l3)) = self.instructions.get_mut(jmp_pc.0) {
-            *l3 = self.pc;
-        } else {
-            unreachable!(
-                "Expected an Instruction::Jmp at PC {}, but found a different instruction",
-                jmp_pc.0
-            );
+        for jmp_pc in jmp_pcs {
+            if let Some(Instruction::Jmp(l3)) = self.instructions.get_mut(jmp_pc.0) {
+                *l3 = self.pc;
+            } else {
+                return Err(GenerateCodeError::Internal {
+                    expected: "Jmp",
+                    pc: jmp_pc.0,
+                });
+            }
         }
 
         Ok(())
@@ -157,10 +257,10 @@ impl CodeGenerator {
         if let Some(Instruction::Split(_, l2)) = self.instructions.get_mut(split_pc.0) {
             *l2 = self.pc;
         } else {
-            unreachable!(
-                "Expected an Instruction::Split at PC {}, but found a different instruction",
-                split_pc.0
-            );
+            return Err(GenerateCodeError::Internal {
+                expected: "Split",
+                pc: split_pc.0,
+            });
         }
 
         Ok(())
@@ -175,13 +275,25 @@ impl CodeGenerator {
     ///     jmp L1
     /// L3:
     /// ```
+    ///
+    /// If e can match the empty string, the loop checks that every iteration consumes a char:
+    /// ```txt
+    ///     loop_start n
+    /// L1: split L2, L3
+    /// L2: e code
+    ///     loop_check n
+    ///     jmp L1
+    /// L3:
+    /// ```
     fn star(&mut self, e: Ast) -> Result<(), GenerateCodeError> {
         assert_eq!(self.instructions.len(), self.pc.0);
 
+        let check = self.loop_start(&e, false)?;
         let l1 = self.pc;
         let l2 = self.pc.inc(|| GenerateCodeError::PcOverflow)?;
         self.instructions.push(Instruction::Split(l2, Pc(0))); // L3 TBD
         self.expr(e)?;
+        self.loop_check(check)?;
         assert_eq!(self.instructions.len(), self.pc.0);
 
         self.pc.inc(|| GenerateCodeError::PcOverflow)?;
@@ -191,10 +303,10 @@ impl CodeGenerator {
         if let Some(Instruction::Split(_, l3)) = self.instructions.get_mut(l1.0) {
             *l3 = self.pc;
         } else {
-            unreachable!(
-                "Expected an Instruction::Split at PC {}, but found a different instruction",
-                l1.0
-            );
+            return Err(GenerateCodeError::Internal {
+                expected: "Split",
+                pc: l1.0,
+            });
         }
 
         Ok(())
@@ -208,11 +320,22 @@ impl CodeGenerator {
     ///     split L1, L2
     /// L2:
     /// ```
+    ///
+    /// If e can match the empty string, the loop checks that every iteration but the first consumes a char:
+    /// ```txt
+    ///     loop_start n, first may be empty
+    /// L1: e code
+    ///     loop_check n
+    ///     split L1, L2
+    /// L2:
+    /// ```
     fn plus(&mut self, e: Ast) -> Result<(), GenerateCodeError> {
         assert_eq!(self.instructions.len(), self.pc.0);
 
+        let check = self.loop_start(&e, true)?;
         let l1 = self.pc;
         self.expr(e)?;
+        self.loop_check(check)?;
         assert_eq!(self.instructions.len(), self.pc.0);
 
         let l2 = self.pc.inc(|| GenerateCodeError::PcOverflow)?;
@@ -222,31 +345,349 @@ impl CodeGenerator {
         Ok(())
     }
 
+    /// Generate loop_start instruction if the loop body `e` can match the empty string, and return the number of the
+    /// loop for its loop_check.
+    fn loop_start(
+        &mut self,
+        e: &Ast,
+        first_may_be_empty: bool,
+    ) -> Result<Option<usize>, GenerateCodeError> {
+        if e.min_len() > 0 {
+            return Ok(None);
+        }
+        let n = self.loops;
+        self.loops += 1;
+        self.instructions
+            .push(Instruction::LoopStart(n, first_may_be_empty));
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        Ok(Some(n))
+    }
+
+    /// Generate loop_check instruction for the loop that `loop_start` numbered, if any.
+    fn loop_check(&mut self, n: Option<usize>) -> Result<(), GenerateCodeError> {
+        if let Some(n) = n {
+            self.instructions.push(Instruction::LoopCheck(n));
+            self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Generate ascii_class instruction.
+    fn ascii_class(&mut self, bits: [u64; 2]) -> Result<(), GenerateCodeError> {
+        self.instructions.push(Instruction::AsciiClass(bits));
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        Ok(())
+    }
+
+    /// Generate range instruction.
+    fn range(&mut self, lo: char, hi: char) -> Result<(), GenerateCodeError> {
+        self.instructions.push(Instruction::Range(lo, hi));
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        Ok(())
+    }
+
     /// Generate code for Dot operator.
     ///
     /// .
     /// ```txt
     ///    any_byte
     /// ```
-    fn dot(&mut self) -> Result<(), GenerateCodeError> {
+    /// It is any_byte_except_newline if `.` does not match `\n`, as compiled or under the inline flag `-s`.
+    fn dot(&mut self, except_newline: bool) -> Result<(), GenerateCodeError> {
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        self.instructions.push(if except_newline {
+            Instruction::AnyByteExceptNewline
+        } else {
+            Instruction::AnyByte
+        });
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        Ok(())
+    }
+
+    /// Generate code for lookahead assertions.
+    ///
+    /// (?=e)
+    /// ```txt
+    ///     look_ahead L1
+    ///     e code
+    ///     match
+    /// L1:
+    /// ```
+    /// (?!e) is the same with negative_look_ahead.
+    fn look_ahead(&mut self, e: Ast, negative: bool) -> Result<(), GenerateCodeError> {
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        let look_pc = self.pc;
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        // L1 TBD.
+        self.instructions.push(if negative {
+            Instruction::NegativeLookAhead(Pc(0))
+        } else {
+            Instruction::LookAhead(Pc(0))
+        });
+        self.expr(e)?;
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        self.instructions.push(Instruction::Match);
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        if let Some(Instruction::LookAhead(l1) | Instruction::NegativeLookAhead(l1)) =
+            self.instructions.get_mut(look_pc.0)
+        {
+            *l1 = self.pc;
+        } else {
+            return Err(GenerateCodeError::Internal {
+                expected: "LookAhead",
+                pc: look_pc.0,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Generate code for lookbehind assertions, whose operand matches `width` chars.
+    ///
+    /// The machine runs the operand from `width` chars before, so an operand of another or a variable width, which
+    /// the parser rejects, is unsupported.
+    ///
+    /// (?<=e)
+    /// ```txt
+    ///     look_behind L1, width
+    ///     e code
+    ///     match
+    /// L1:
+    /// ```
+    /// (?<!e) is the same with negative_look_behind.
+    fn look_behind(
+        &mut self,
+        e: Ast,
+        width: usize,
+        negative: bool,
+    ) -> Result<(), GenerateCodeError> {
         assert_eq!(self.instructions.len(), self.pc.0);
 
-        self.instructions.push(Instruction::AnyByte);
+        if e.min_len() != width || e.max_len() != Some(width) {
+            return Err(GenerateCodeError::UnsupportedConstruct {
+                construct: "lookbehind of a width other than its operand's",
+            });
+        }
+
+        let look_pc = self.pc;
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        // L1 TBD.
+        self.instructions.push(if negative {
+            Instruction::NegativeLookBehind(Pc(0), width)
+        } else {
+            Instruction::LookBehind(Pc(0), width)
+        });
+        self.expr(e)?;
         self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        self.instructions.push(Instruction::Match);
         assert_eq!(self.instructions.len(), self.pc.0);
 
+        if let Some(Instruction::LookBehind(l1, _) | Instruction::NegativeLookBehind(l1, _)) =
+            self.instructions.get_mut(look_pc.0)
+        {
+            *l1 = self.pc;
+        } else {
+            return Err(GenerateCodeError::Internal {
+                expected: "LookBehind",
+                pc: look_pc.0,
+            });
+        }
+
         Ok(())
     }
+
+    /// Generate code for the `\G` assertion.
+    ///
+    /// \G
+    /// ```txt
+    ///    search_start
+    /// ```
+    fn search_start(&mut self) -> Result<(), GenerateCodeError> {
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        self.instructions.push(Instruction::SearchStart);
+        self.pc.inc(|| GenerateCodeError::PcOverflow)?;
+        assert_eq!(self.instructions.len(), self.pc.0);
+
+        Ok(())
+    }
+}
+
+/// Bitmap of the chars that an alternation of the operands matches, if they are all ASCII chars or ranges and cover
+/// more than two chars, e.g. `[a-z_]` or `a|b|c`.
+///
+/// Each operand consumes a single char and the same code follows, so the order in which they are tried does not
+/// change the matches. One or two chars, as in `a|b`, are left to the split, which tests them as quickly.
+fn ascii_class(operands: &[Ast]) -> Option<[u64; 2]> {
+    let mut bits = [0u64; 2];
+    for operand in operands {
+        let (lo, hi) = match *operand {
+            Ast::Char(c) => (c, c),
+            Ast::Range(lo, hi) => (lo, hi),
+            _ => return None,
+        };
+        if !hi.is_ascii() {
+            return None;
+        }
+        for c in lo as usize..=hi as usize {
+            bits[c / 64] |= 1 << (c % 64);
+        }
+    }
+    (bits[0].count_ones() + bits[1].count_ones() > 2).then_some(bits)
+}
+
+/// Apply `f` to every PC that the instruction may continue at, other than the next one.
+fn map_targets(instruction: &mut Instruction, mut f: impl FnMut(Pc) -> Pc) {
+    match instruction {
+        Instruction::Jmp(target)
+        | Instruction::LookAhead(target)
+        | Instruction::NegativeLookAhead(target)
+        | Instruction::LookBehind(target, _)
+        | Instruction::NegativeLookBehind(target, _) => *target = f(*target),
+        Instruction::Split(l1, l2) => {
+            *l1 = f(*l1);
+            *l2 = f(*l2);
+        }
+        Instruction::Char(_)
+        | Instruction::Match
+        | Instruction::Range(..)
+        | Instruction::AsciiClass(_)
+        | Instruction::AnyByte
+        | Instruction::AnyByteExceptNewline
+        | Instruction::SearchStart
+        | Instruction::LoopStart(..)
+        | Instruction::LoopCheck(_)
+        | Instruction::Fail => {}
+    }
+}
+
+/// Check which instructions a thread can reach from PC 0, by their PC. A target out of range is ignored.
+pub(crate) fn reachable(instructions: &[Instruction]) -> Vec<bool> {
+    let mut reached = vec![false; instructions.len()];
+    let mut pcs = Vec::from([Pc(0)]);
+    while let Some(pc) = pcs.pop() {
+        match reached.get_mut(pc.0) {
+            Some(reached) if !*reached => *reached = true,
+            _ => continue,
+        }
+        let next = Pc(pc.0 + 1);
+        match instructions[pc.0] {
+            Instruction::Char(_)
+            | Instruction::Range(..)
+            | Instruction::AsciiClass(_)
+            | Instruction::AnyByte
+            | Instruction::AnyByteExceptNewline
+            | Instruction::SearchStart
+            | Instruction::LoopStart(..)
+            | Instruction::LoopCheck(_) => pcs.push(next),
+            Instruction::Jmp(target) => pcs.push(target),
+            Instruction::Split(l1, l2) => pcs.extend([l1, l2]),
+            // The separate search runs from the next PC.
+            Instruction::LookAhead(target)
+            | Instruction::NegativeLookAhead(target)
+            | Instruction::LookBehind(target, _)
+            | Instruction::NegativeLookBehind(target, _) => pcs.extend([next, target]),
+            Instruction::Match | Instruction::Fail => {}
+        }
+    }
+    reached
+}
+
+/// Remove the `Jmp` instructions that the program can do without, keeping its matches unchanged:
+///
+/// - A jump to a `Jmp` goes to the final target of the chain directly, and so does any other instruction.
+/// - A jump to `Match` or `Fail` is replaced with that instruction, which does the same wherever it runs. This
+///   removes the jump at the end of every alternative but the last of a top-level alternation, e.g. `a|b`.
+/// - The instructions that no thread reaches anymore are dropped, such as a jump that every instruction now goes
+///   past, e.g. the one closing the loop of `a*` in `(a*|b)+`.
+/// - A jump to the next instruction kept is dropped, e.g. in `ab(c|)`.
+///
+/// The PCs after a dropped instruction are shifted.
+pub fn remove_jumps(mut instructions: Vec<Instruction>) -> Vec<Instruction> {
+    // Final target of the chain of jumps from `target`, which has at most as many jumps as the program.
+    let resolve = |instructions: &[Instruction], mut target: Pc| {
+        for _ in 0..instructions.len() {
+            match instructions.get(target.0) {
+                Some(Instruction::Jmp(next)) => target = *next,
+                _ => break,
+            }
+        }
+        target
+    };
+
+    let original = instructions.clone();
+    for instruction in &mut instructions {
+        map_targets(instruction, |target| resolve(&original, target));
+        if let Instruction::Jmp(target) = *instruction {
+            if let Some(end @ (Instruction::Match | Instruction::Fail)) = original.get(target.0) {
+                *instruction = *end;
+            }
+        }
+    }
+
+    // Decide from the end which instructions are kept, so that a jump is dropped if the instructions up to its
+    // target are, and `next_kept[pc]` is the first one kept from `pc` on.
+    let mut keep = reachable(&instructions);
+    let mut next_kept = vec![Pc(instructions.len()); instructions.len() + 1];
+    for pc in (0..instructions.len()).rev() {
+        if let Instruction::Jmp(target) = instructions[pc] {
+            if next_kept.get(target.0) == Some(&next_kept[pc + 1]) {
+                keep[pc] = false;
+            }
+        }
+        next_kept[pc] = if keep[pc] { Pc(pc) } else { next_kept[pc + 1] };
+    }
+
+    // New PC of every instruction, which is that of the next one kept for a dropped one.
+    let mut new_pcs = Vec::with_capacity(instructions.len() + 1);
+    let mut kept = 0;
+    for &keep in &keep {
+        new_pcs.push(Pc(kept));
+        if keep {
+            kept += 1;
+        }
+    }
+    new_pcs.push(Pc(kept));
+
+    instructions
+        .into_iter()
+        .zip(keep)
+        .filter(|&(_, keep)| keep)
+        .map(|(mut instruction, _)| {
+            map_targets(&mut instruction, |target| {
+                new_pcs.get(target.0).copied().unwrap_or(target)
+            });
+            instruction
+        })
+        .collect()
 }
 
-/// Generate code for the given AST.
-pub fn generate_code(ast: Ast) -> Result<Vec<Instruction>, GenerateCodeError> {
-    CodeGenerator::default().generate_code(ast)
+/// Generate code for the given AST, in which `.` matches `\n` only if `dot_matches_newline`.
+///
+/// With `ascii_classes`, an alternation of ASCII chars and ranges such as `[a-z_]` is tested at once with a bitmap,
+/// which is only correct if the machine compares chars with `==`.
+pub fn generate_code(
+    ast: Ast,
+    dot_matches_newline: bool,
+    ascii_classes: bool,
+) -> Result<Vec<Instruction>, GenerateCodeError> {
+    CodeGenerator {
+        dot_except_newline: !dot_matches_newline,
+        ascii_classes,
+        ..Default::default()
+    }
+    .generate_code(ast)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::machine::{Machine, Sp};
 
     #[test]
     fn concat() {
@@ -363,6 +804,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn loop_check() {
+        // (a?)*(\G)+, whose operands can match the empty string.
+        let gen = CodeGenerator::default();
+        let ast = Ast::Concat(vec![
+            Ast::Star(Ast::Question(Ast::Char('a').into()).into()),
+            Ast::Plus(Ast::SearchStart.into()),
+        ]);
+        assert_eq!(
+            gen.generate_code(ast).unwrap(),
+            vec![
+                /*   :0 */ Instruction::LoopStart(0, false),
+                /* L1:1 */ Instruction::Split(Pc(2), Pc(6)), // L2, L3
+                /* L2:2 */ Instruction::Split(Pc(3), Pc(4)),
+                /*   :3 */ Instruction::Char('a'),
+                /*   :4 */ Instruction::LoopCheck(0),
+                /*   :5 */ Instruction::Jmp(Pc(1)),
+                /* L3:6 */ Instruction::LoopStart(1, true),
+                /* L1:7 */ Instruction::SearchStart,
+                /*   :8 */ Instruction::LoopCheck(1),
+                /*   :9 */ Instruction::Split(Pc(7), Pc(10)), // L1, L2
+                /* L2:10 */ Instruction::Match,
+            ]
+        );
+    }
+
     #[test]
     fn dot() {
         // .
@@ -386,4 +853,298 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn group_plus() {
+        // (ab)+c
+        let gen = CodeGenerator::default();
+        let ast = Ast::Concat(vec![
+            Ast::Plus(Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into()),
+            Ast::Char('c'),
+        ]);
+        assert_eq!(
+            gen.generate_code(ast).unwrap(),
+            vec![
+                /* L1:0 */ Instruction::Char('a'),
+                /*   :1 */ Instruction::Char('b'),
+                /*   :2 */ Instruction::Split(Pc(0), Pc(3)), // L1, L2
+                /* L2:3 */ Instruction::Char('c'),
+                /*   :4 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn long_or() {
+        let n = 5000;
+        let ast = (0..n)
+            .map(|i| Ast::Char(char::from_u32(0x4e00 + i).unwrap()))
+            .rev()
+            .reduce(|rhs, lhs| Ast::Or(lhs.into(), rhs.into()))
+            .unwrap();
+        let instructions = generate_code(ast, true, false).unwrap();
+
+        // split, char and jmp for each branch but the last, which has only a char.
+        let n = n as usize;
+        assert_eq!(instructions.len(), 3 * (n - 1) + 1 + 1);
+        assert_eq!(instructions[0], Instruction::Split(Pc(1), Pc(3)));
+        assert_eq!(instructions[1], Instruction::Char('\u{4e00}'));
+        assert_eq!(instructions[2], Instruction::Jmp(Pc(3 * (n - 1) + 1)));
+        assert_eq!(instructions[3 * (n - 1)], Instruction::Char('\u{6187}'));
+        assert_eq!(instructions.last(), Some(&Instruction::Match));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            generate_code(Ast::Empty, true, false).unwrap(),
+            [Instruction::Match]
+        );
+    }
+
+    #[test]
+    fn look_ahead() {
+        // a(?=b)
+        let ast = Ast::Concat(vec![Ast::Char('a'), Ast::LookAhead(Ast::Char('b').into())]);
+        assert_eq!(
+            generate_code(ast, true, false).unwrap(),
+            vec![
+                /*   :0 */ Instruction::Char('a'),
+                /*   :1 */ Instruction::LookAhead(Pc(4)), // L1
+                /*   :2 */ Instruction::Char('b'),
+                /*   :3 */ Instruction::Match,
+                /* L1:4 */ Instruction::Match,
+            ]
+        );
+
+        let ast = Ast::NegativeLookAhead(Ast::Dot.into());
+        assert_eq!(
+            generate_code(ast, true, false).unwrap(),
+            vec![
+                /*   :0 */ Instruction::NegativeLookAhead(Pc(3)), // L1
+                /*   :1 */ Instruction::AnyByte,
+                /*   :2 */ Instruction::Match,
+                /* L1:3 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn look_behind() {
+        // (?<=a)b
+        let ast = Ast::Concat(vec![
+            Ast::LookBehind(Ast::Char('a').into(), 1),
+            Ast::Char('b'),
+        ]);
+        assert_eq!(
+            generate_code(ast, true, false).unwrap(),
+            vec![
+                /*   :0 */ Instruction::LookBehind(Pc(3), 1), // L1
+                /*   :1 */ Instruction::Char('a'),
+                /*   :2 */ Instruction::Match,
+                /* L1:3 */ Instruction::Char('b'),
+                /*   :4 */ Instruction::Match,
+            ]
+        );
+
+        let ast = Ast::NegativeLookBehind(Ast::Concat(vec![Ast::Dot, Ast::Dot]).into(), 2);
+        assert_eq!(
+            generate_code(ast, true, false).unwrap(),
+            vec![
+                /*   :0 */ Instruction::NegativeLookBehind(Pc(4), 2), // L1
+                /*   :1 */ Instruction::AnyByte,
+                /*   :2 */ Instruction::AnyByte,
+                /*   :3 */ Instruction::Match,
+                /* L1:4 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_jumps() {
+        let jmps = |instructions: &[Instruction]| {
+            instructions
+                .iter()
+                .filter(|i| matches!(i, Instruction::Jmp(_)))
+                .count()
+        };
+
+        // a|b
+        let ast = Ast::Or(Ast::Char('a').into(), Ast::Char('b').into());
+        let naive = generate_code(ast, true, false).unwrap();
+        let removed = super::remove_jumps(naive.clone());
+        assert_eq!(
+            removed,
+            vec![
+                /*   :0 */ Instruction::Split(Pc(1), Pc(3)), // L1, L2
+                /* L1:1 */ Instruction::Char('a'),
+                /*   :2 */ Instruction::Match,
+                /* L2:3 */ Instruction::Char('b'),
+                /*   :4 */ Instruction::Match,
+            ]
+        );
+        assert!(jmps(&removed) < jmps(&naive));
+
+        // x(y|())z, where the jump after `y` goes to the next instruction.
+        let ast = Ast::Concat(vec![
+            Ast::Char('x'),
+            Ast::Or(Ast::Char('y').into(), Ast::Empty.into()),
+            Ast::Char('z'),
+        ]);
+        let naive = generate_code(ast, true, false).unwrap();
+        assert_eq!(
+            super::remove_jumps(naive),
+            vec![
+                /*   :0 */ Instruction::Char('x'),
+                /*   :1 */ Instruction::Split(Pc(2), Pc(3)), // L1, L2
+                /* L1:2 */ Instruction::Char('y'),
+                /* L2:3 */ Instruction::Char('z'),
+                /*   :4 */ Instruction::Match,
+            ]
+        );
+
+        // The matches are unchanged, including the loops and the lookarounds.
+        for pattern in [
+            "a|b",
+            "(ab|c)*d",
+            "x(y|())z",
+            "(a|b(?=c)|())d",
+            "(?<=a|b)c|d",
+            "(a|b|c)?",
+        ] {
+            let naive = generate_code(crate::parser::parse(pattern).unwrap(), true, false).unwrap();
+            let removed = super::remove_jumps(naive.clone());
+            assert!(jmps(&removed) <= jmps(&naive));
+            let (naive, removed) = (Machine::new(naive), Machine::new(removed));
+            removed.validate().unwrap();
+            for text in [
+                "", "a", "b", "ab", "abd", "cd", "abcd", "xz", "xyz", "bc", "ac", "d", "ba",
+            ] {
+                let chars = text.chars().collect::<Vec<_>>();
+                assert_eq!(
+                    removed.longest_match_end(&chars, Sp(0), Sp(0)).unwrap(),
+                    naive.longest_match_end(&chars, Sp(0), Sp(0)).unwrap(),
+                    "{pattern} {text}"
+                );
+                assert_eq!(
+                    removed.match_end(&chars, Sp(0), Sp(0)).unwrap(),
+                    naive.match_end(&chars, Sp(0), Sp(0)).unwrap(),
+                    "{pattern} {text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_construct() {
+        // (?<=a+), which the parser rejects.
+        let ast = Ast::LookBehind(Ast::Plus(Ast::Char('a').into()).into(), 1);
+        assert!(matches!(
+            generate_code(ast, true, false),
+            Err(GenerateCodeError::UnsupportedConstruct { .. })
+        ));
+
+        // (?<!ab) with a wrong width.
+        let ast = Ast::Concat(vec![
+            Ast::Char('x'),
+            Ast::NegativeLookBehind(Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into(), 1),
+        ]);
+        assert!(matches!(
+            generate_code(ast, true, false),
+            Err(GenerateCodeError::UnsupportedConstruct { .. })
+        ));
+    }
+
+    #[test]
+    fn internal() {
+        // The first char overwrites the split or jmp that its operator backpatches after it.
+        for (ast, pc, expected) in [
+            (
+                Ast::Or(Ast::Char('a').into(), Ast::Char('b').into()),
+                0,
+                "Split",
+            ),
+            (Ast::Or(Ast::Empty.into(), Ast::Char('b').into()), 1, "Jmp"),
+            (Ast::Question(Ast::Char('a').into()), 0, "Split"),
+            (Ast::Star(Ast::Char('a').into()), 0, "Split"),
+        ] {
+            let gen = CodeGenerator {
+                corrupt: Some((Pc(pc), Instruction::Fail)),
+                ..Default::default()
+            };
+            match gen.generate_code(ast) {
+                Err(GenerateCodeError::Internal { expected: e, pc: p }) => {
+                    assert_eq!((e, p), (expected, pc))
+                }
+                res => panic!("{res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn dot_except_newline() {
+        // a.
+        let ast = Ast::Concat(vec![Ast::Char('a'), Ast::Dot]);
+        assert_eq!(
+            generate_code(ast, false, false).unwrap(),
+            vec![
+                /*   :0 */ Instruction::Char('a'),
+                /*   :1 */ Instruction::AnyByteExceptNewline,
+                /*   :2 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn range() {
+        // [a-z]+
+        let ast = Ast::Plus(Ast::Range('a', 'z').into());
+        assert_eq!(
+            generate_code(ast, true, false).unwrap(),
+            vec![
+                /* L1:0 */ Instruction::Range('a', 'z'),
+                /*   :1 */ Instruction::Split(Pc(0), Pc(2)), // L1, L2
+                /* L2:2 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn ascii_class() {
+        // [A-C_a]
+        let ast = Ast::Or(
+            Ast::Range('A', 'C').into(),
+            Ast::Or(Ast::Char('_').into(), Ast::Char('a').into()).into(),
+        );
+        assert_eq!(
+            generate_code(ast.clone(), true, true).unwrap(),
+            vec![
+                /*   :0 */
+                Instruction::AsciiClass([0, 0b1110 | 1 << (b'_' - 64) | 1 << (b'a' - 64)]),
+                /*   :1 */ Instruction::Match,
+            ]
+        );
+        // Without ascii_classes, the operands are split as in `or`.
+        assert_eq!(generate_code(ast, true, false).unwrap().len(), 8);
+
+        // a|b+ and a|é are not classes, and a|b is left to a split.
+        let ast = Ast::Or(
+            Ast::Char('a').into(),
+            Ast::Plus(Ast::Char('b').into()).into(),
+        );
+        assert_eq!(
+            generate_code(ast, true, true).unwrap()[0],
+            Instruction::Split(Pc(1), Pc(3))
+        );
+        let ast = Ast::Or(Ast::Char('a').into(), Ast::Char('é').into());
+        assert_eq!(
+            generate_code(ast, true, true).unwrap()[0],
+            Instruction::Split(Pc(1), Pc(3))
+        );
+        let ast = Ast::Or(Ast::Char('a').into(), Ast::Char('b').into());
+        assert_eq!(
+            generate_code(ast, true, true).unwrap()[0],
+            Instruction::Split(Pc(1), Pc(3))
+        );
+    }
 }
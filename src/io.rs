@@ -0,0 +1,139 @@
+//! Matching over readers and writers, which needs the `std` feature.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{expand, Regex};
+
+/// Read the lines of the reader, and yield those that the regular expression matches somewhere, as
+/// [`Regex::is_match`] does.
+///
+/// The lines are yielded without their line terminator. An error of reading, or of matching as
+/// [`io::ErrorKind::Other`], is yielded in place of the line, and the iteration can go on with the next line.
+///
+/// # Example
+/// ```
+/// use std::io::Cursor;
+///
+/// use vmregex::{io::match_lines, Regex};
+///
+/// let re = Regex::new("b+").unwrap();
+/// let lines = match_lines(&re, Cursor::new("abc\nxyz\nbb\n"))
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(lines, ["abc", "bb"]);
+/// ```
+pub fn match_lines<'r, R: BufRead + 'r>(
+    re: &'r Regex,
+    reader: R,
+) -> impl Iterator<Item = io::Result<String>> + 'r {
+    // The buffers of the matcher are reused across the lines.
+    let mut matcher = re.matcher();
+    reader.lines().filter_map(move |line| match line {
+        Ok(line) => match matcher.is_match(&line) {
+            Ok(true) => Some(Ok(line)),
+            Ok(false) => None,
+            Err(e) => Some(Err(io::Error::other(e))),
+        },
+        Err(e) => Some(Err(e)),
+    })
+}
+
+impl Regex {
+    /// Replace every match in the text with the template `rep` as [`Regex::replace_all`] does, but write the result
+    /// to `out` piece by piece instead of building it in memory.
+    ///
+    /// An error of matching is returned as [`io::ErrorKind::Other`], once the text up to the end of the previous match
+    /// has been written.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let mut out = Vec::new();
+    /// re.replace_all_stream("a1b22", "<$0>", &mut out).unwrap();
+    /// assert_eq!(out, b"a<1>b<22>");
+    /// ```
+    pub fn replace_all_stream<W: Write>(
+        &self,
+        text: &str,
+        rep: &str,
+        out: &mut W,
+    ) -> io::Result<()> {
+        // The buffer of a replacement is reused across the matches.
+        let mut replacement = String::new();
+        let mut last = 0;
+        for m in self.find_iter(text) {
+            let m = m.map_err(io::Error::other)?;
+            out.write_all(&text.as_bytes()[last..m.byte_start])?;
+            replacement.clear();
+            expand(rep, m.as_str(), &mut replacement);
+            out.write_all(replacement.as_bytes())?;
+            last = m.byte_end;
+        }
+        out.write_all(&text.as_bytes()[last..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::machine::MAX_DEPTH;
+
+    fn matching(pattern: &str, text: &str) -> Vec<String> {
+        let re = Regex::new(pattern).unwrap();
+        super::match_lines(&re, Cursor::new(text))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn match_lines() {
+        let text = "root:x:0:0\r\ndaemon:x:1:1\nbin:x:2:2\nsys:x:3:3";
+        assert_eq!(matching("o+t", text), ["root:x:0:0"]);
+        assert_eq!(matching("s+y", text), ["sys:x:3:3"]);
+        assert_eq!(matching("x", text).len(), 4);
+        assert!(matching("nobody", text).is_empty());
+        assert!(matching("a", "").is_empty());
+        // Empty lines are yielded if the pattern matches the empty string.
+        assert_eq!(matching("a*", "\n\nb"), ["", "", "b"]);
+
+        // An error of matching is yielded in place of the line.
+        let re = Regex::new("a*c").unwrap();
+        let text = format!("ac\n{}c\nc", "a".repeat(MAX_DEPTH + 1));
+        let lines = super::match_lines(&re, Cursor::new(text)).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].as_ref().unwrap(), "ac");
+        assert_eq!(lines[1].as_ref().unwrap_err().kind(), io::ErrorKind::Other);
+        assert_eq!(lines[2].as_ref().unwrap(), "c");
+    }
+
+    #[test]
+    fn replace_all_stream() {
+        for (pattern, text, rep) in [
+            ("a*", "baaab", "X"),
+            ("o+", "foo boo", "0"),
+            ("[0-9]+", "id=12 port=8080", "<$0>"),
+            ("é+", "aéébé", "${0}$$"),
+            ("x", "", "y"),
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            let mut out = Vec::new();
+            re.replace_all_stream(text, rep, &mut out).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                re.replace_all(text, rep).unwrap()
+            );
+        }
+
+        // An error of matching is returned after the text up to the previous match is written.
+        let re = Regex::new("a*c").unwrap();
+        let text = format!("ac {}c", "a".repeat(MAX_DEPTH + 1));
+        let mut out = Vec::new();
+        let e = re.replace_all_stream(&text, "x", &mut out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert_eq!(out, b"x");
+    }
+}
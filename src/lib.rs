@@ -1,12 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod codegen;
+#[cfg(feature = "std")]
+pub mod io;
 mod machine;
+mod optimizer;
 mod parser;
 
-use codegen::GenerateCodeError;
-use machine::{Machine, MatchError};
-use parser::ParseError;
+pub use codegen::{GenerateCodeError, Instruction, Pc};
+pub use machine::{MatchError, MatchStats, ValidationError};
+pub use parser::{parse, Ast, ParseError};
 
-use thiserror::Error;
+use machine::{Machine, Mode, Sp, Threads};
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, ops::Range};
 
 /// Regular expression.
 ///
@@ -24,28 +34,2878 @@ use thiserror::Error;
 /// ```
 pub struct Regex {
     machine: Machine,
+    // Program compiled from the reversed pattern, used to match from the end of the text. There is none unless
+    // `RegexBuilder::reverse_program` is set, or if the pattern has a lookahead, which cannot be reversed.
+    reverse_machine: Option<Machine>,
+    // Minimum length of a match in chars, to reject a short text without running the machine.
+    min_len: usize,
+    max_len: Option<usize>,
+    anchored_start: bool,
+    // Chars that every match contains, to reject a text without one of them before running the machine.
+    required_chars: Vec<char>,
+    required_literals: Vec<String>,
+    // Simplified AST that the programs are generated from, kept for `canonical_key` and `alternation_overlaps`.
+    ast: Ast,
+    dot_matches_newline: bool,
+    max_input_len: Option<usize>,
+    // Threads that a new matcher has room for, or 0 to let the stack grow as needed.
+    threads_capacity: usize,
+    offset_mode: OffsetMode,
+    // Whether the pattern was written in hex, so that its chars stand for bytes.
+    hex: bool,
+}
+
+/// Unit of the offsets into a text, chosen with [`RegexBuilder::offset_mode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetMode {
+    /// Offsets count chars, as the machine matches the text char by char.
+    #[default]
+    Char,
+    /// Offsets count bytes, e.g. to slice the text with them.
+    Byte,
+}
+
+/// Single match of a regular expression in a text.
+///
+/// The offsets are counted in chars, or in bytes with [`OffsetMode::Byte`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+    // Byte offsets of the match in `text`.
+    byte_start: usize,
+    byte_end: usize,
+}
+
+impl<'t> Match<'t> {
+    /// Offset of the first char of the match, in the unit of [`RegexBuilder::offset_mode`].
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Offset just past the last char of the match, in the unit of [`RegexBuilder::offset_mode`].
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Byte range of the match, to slice the text with, unlike [`Match::start`] and [`Match::end`].
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let text = "ö+ab";
+    /// let m = Regex::new("ab").unwrap().find(text).unwrap().unwrap();
+    /// assert_eq!((m.start(), m.range()), (2, 3..5));
+    /// assert_eq!(&text[m.range()], "ab");
+    /// ```
+    pub fn range(&self) -> Range<usize> {
+        self.byte_start..self.byte_end
+    }
+
+    /// Matched part of the text.
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.range()]
+    }
+}
+
+/// Iterator over the successive non-overlapping matches in a text, created by [`Regex::find_iter`].
+pub struct Matches<'r, 't> {
+    matcher: Matcher<'r>,
+    text: &'t str,
+    // Where the next search starts, or `None` once the iterator is exhausted.
+    start: Option<Sp>,
+    last_end: Option<Sp>,
+    // Error to yield before searching, if the text is too long.
+    error: Option<MatchError>,
+}
+
+impl<'t> Iterator for Matches<'_, 't> {
+    type Item = Result<Match<'t>, MatchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.start = None;
+            return Some(Err(e));
+        }
+        loop {
+            let (start, end) = match self.matcher.search_at(self.start?) {
+                Ok(Some(m)) => m,
+                Ok(None) => {
+                    self.start = None;
+                    return None;
+                }
+                Err(e) => {
+                    self.start = None;
+                    return Some(Err(e));
+                }
+            };
+
+            // After an empty match, advance by one char so that the search makes progress.
+            self.start = if start == end {
+                Some(Sp(end.0 + 1)).filter(|sp| sp.0 <= self.matcher.chars.len())
+            } else {
+                Some(end)
+            };
+            // An empty match right where the previous match ended is not reported.
+            if start == end && self.last_end == Some(end) {
+                continue;
+            }
+            self.last_end = Some(end);
+
+            return Some(Ok(self.matcher.get_match(self.text, start, end)));
+        }
+    }
+}
+
+/// Handle to match a [`Regex`] many times, created by [`Regex::matcher`].
+///
+/// It keeps the buffers used while matching, so that matching again does not allocate them anew once they are large
+/// enough. The [`Regex`] itself holds no such state and can be shared across threads, each making its own matcher.
+///
+/// # Example
+/// ```
+/// use vmregex::Regex;
+///
+/// let re = Regex::new("ab+").unwrap();
+/// let mut matcher = re.matcher();
+/// assert!(matcher.is_match("abb").unwrap());
+/// assert!(!matcher.is_match("ba").unwrap());
+/// assert_eq!(matcher.find("babb").unwrap().unwrap().range(), 1..4);
+/// ```
+pub struct Matcher<'r> {
+    regex: &'r Regex,
+    // Chars of the text being matched.
+    chars: Vec<char>,
+    // Byte offset of every char, followed by the length of the text, in order to slice out matches.
+    offsets: Vec<usize>,
+    threads: Threads,
+}
+
+impl Matcher<'_> {
+    /// Check the length of the text against [`RegexBuilder::max_input_len`] before reading it.
+    fn check_len(&self, len: usize) -> Result<(), MatchError> {
+        self.regex.check_len(len)
+    }
+
+    /// Split the text into chars, remembering the byte offset of each char.
+    fn load(&mut self, text: &str) {
+        self.chars.clear();
+        self.offsets.clear();
+        for (offset, c) in text.char_indices() {
+            self.chars.push(c);
+            self.offsets.push(offset);
+        }
+        self.offsets.push(text.len());
+    }
+
+    fn get_match<'t>(&self, text: &'t str, start: Sp, end: Sp) -> Match<'t> {
+        let (byte_start, byte_end) = (self.offsets[start.0], self.offsets[end.0]);
+        let (start, end) = match self.regex.offset_mode {
+            OffsetMode::Char => (start.0, end.0),
+            OffsetMode::Byte => (byte_start, byte_end),
+        };
+        Match {
+            text,
+            start,
+            end,
+            byte_start,
+            byte_end,
+        }
+    }
+
+    /// String pointer at the offset of the loaded text, in the unit of [`RegexBuilder::offset_mode`].
+    fn sp_at(&self, offset: usize) -> Result<Sp, MatchError> {
+        match self.regex.offset_mode {
+            OffsetMode::Char => Ok(Sp(offset)),
+            // An offset past the end finds no match, as in chars.
+            OffsetMode::Byte if offset > self.offsets[self.chars.len()] => {
+                Ok(Sp(self.chars.len() + 1))
+            }
+            OffsetMode::Byte => self
+                .offsets
+                .binary_search(&offset)
+                .map(Sp)
+                .map_err(|_| MatchError::NotCharBoundary { offset }),
+        }
+    }
+
+    /// Find the leftmost-first match in the loaded text starting at or after `start`.
+    fn search_at(&mut self, search_start: Sp) -> Result<Option<(Sp, Sp)>, MatchError> {
+        self.find_span(search_start, Mode::First)
+    }
+
+    /// Find the leftmost match in the loaded text starting at or after `search_start`, in the mode.
+    fn find_span(&mut self, search_start: Sp, mode: Mode) -> Result<Option<(Sp, Sp)>, MatchError> {
+        // A match of an anchored pattern can only start where `\G` asserts, so no later start is tried.
+        if self.regex.anchored_start {
+            if search_start.0 > self.chars.len() {
+                return Ok(None);
+            }
+            let end = self.regex.machine.run(
+                &mut self.threads,
+                &self.chars,
+                search_start,
+                search_start,
+                mode,
+            )?;
+            return Ok(end.map(|end| (search_start, end)));
+        }
+        self.regex
+            .machine
+            .find_span(&mut self.threads, &self.chars, search_start, mode)
+    }
+
+    /// Check if the regular expression matches somewhere in the text, as [`Regex::is_match`] does.
+    pub fn is_match(&mut self, text: &str) -> Result<bool, MatchError> {
+        self.check_len(text.len())?;
+        // A match of an anchored pattern can only start at the start of the text.
+        if self.regex.anchored_start {
+            return self.is_prefix_match(text);
+        }
+        // A text shorter than any match cannot match. `len` counts bytes, which is not less than the chars.
+        if text.len() < self.regex.min_len {
+            return Ok(false);
+        }
+        // Neither can a text missing a char of every match, which is cheaper to check than searching it.
+        if !self.regex.required_chars.iter().all(|&c| text.contains(c)) {
+            return Ok(false);
+        }
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        Ok(self.search_at(Sp(0))?.is_some())
+    }
+
+    /// Check if the regular expression matches somewhere in the text and count the work done, as
+    /// [`Regex::is_match_with_stats`] does.
+    pub fn is_match_with_stats(&mut self, text: &str) -> Result<(bool, MatchStats), MatchError> {
+        self.check_len(text.len())?;
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        // Every start is tried as in `search_at`, without the shortcuts of `is_match`, so that the whole search is
+        // counted.
+        let last_start = if self.regex.anchored_start {
+            0
+        } else {
+            self.chars.len()
+        };
+        let mut stats = MatchStats::default();
+        for start in 0..=last_start {
+            let end = self.regex.machine.run_with_stats(
+                &mut self.threads,
+                &self.chars,
+                Sp(0),
+                Sp(start),
+                Mode::First,
+                &mut stats,
+            )?;
+            if end.is_some() {
+                return Ok((true, stats));
+            }
+        }
+        Ok((false, stats))
+    }
+
+    /// Check if the regular expression matches at the start of the text, as [`Regex::is_prefix_match`] does.
+    pub fn is_prefix_match(&mut self, text: &str) -> Result<bool, MatchError> {
+        self.check_len(text.len())?;
+        if text.len() < self.regex.min_len {
+            return Ok(false);
+        }
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        let end =
+            self.regex
+                .machine
+                .run(&mut self.threads, &self.chars, Sp(0), Sp(0), Mode::First)?;
+        Ok(end.is_some())
+    }
+
+    /// Check if the regular expression matches at the byte offset of the text, as [`Regex::is_match_at_byte`] does.
+    pub fn is_match_at_byte(&mut self, text: &str, offset: usize) -> Result<bool, MatchError> {
+        if !text.is_char_boundary(offset) {
+            return Err(MatchError::NotCharBoundary { offset });
+        }
+        self.check_len(text.len())?;
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        let start = Sp(text[..offset].chars().count());
+        let end =
+            self.regex
+                .machine
+                .run(&mut self.threads, &self.chars, start, start, Mode::First)?;
+        Ok(end.is_some())
+    }
+
+    /// Check if the whole text matches the regular expression, as [`Regex::is_full_match`] does.
+    pub fn is_full_match(&mut self, text: &str) -> Result<bool, MatchError> {
+        self.check_len(text.len())?;
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        let end =
+            self.regex
+                .machine
+                .run(&mut self.threads, &self.chars, Sp(0), Sp(0), Mode::Full)?;
+        Ok(end.is_some())
+    }
+
+    /// Find the leftmost match in the text, as [`Regex::find`] does.
+    pub fn find<'t>(&mut self, text: &'t str) -> Result<Option<Match<'t>>, MatchError> {
+        self.find_at(text, 0)
+    }
+
+    /// Find the leftmost match in the text that starts at or after the offset `start`, as [`Regex::find_at`] does.
+    pub fn find_at<'t>(
+        &mut self,
+        text: &'t str,
+        start: usize,
+    ) -> Result<Option<Match<'t>>, MatchError> {
+        self.check_len(text.len())?;
+        self.load(text);
+        let start = self.sp_at(start)?;
+        let m = self.search_at(start)?;
+        Ok(m.map(|(start, end)| self.get_match(text, start, end)))
+    }
+
+    /// Find the leftmost-longest match in the text, as [`Regex::longest_match`] does.
+    pub fn longest_match<'t>(&mut self, text: &'t str) -> Result<Option<Match<'t>>, MatchError> {
+        self.check_len(text.len())?;
+        self.load(text);
+        let m = self.find_span(Sp(0), Mode::Longest)?;
+        Ok(m.map(|(start, end)| self.get_match(text, start, end)))
+    }
+
+    /// Length in chars of the longest match at the start of the text, as [`Regex::match_prefix_len`] returns.
+    pub fn match_prefix_len(&mut self, text: &[char]) -> Result<Option<usize>, MatchError> {
+        self.check_len(text.len())?;
+        let end = self
+            .regex
+            .machine
+            .run(&mut self.threads, text, Sp(0), Sp(0), Mode::Longest)?;
+        Ok(end.map(|end| end.0))
+    }
+
+    /// Length of the longest prefix of the text that the program consumes, as [`Regex::consumed_prefix_len`]
+    /// returns.
+    pub fn consumed_prefix_len(&mut self, text: &str) -> Result<usize, MatchError> {
+        self.check_len(text.len())?;
+        self.load(text);
+        let furthest =
+            self.regex
+                .machine
+                .run(&mut self.threads, &self.chars, Sp(0), Sp(0), Mode::Furthest)?;
+        let len = furthest.map_or(0, |sp| sp.0);
+        Ok(match self.regex.offset_mode {
+            OffsetMode::Char => len,
+            OffsetMode::Byte => self.offsets[len],
+        })
+    }
+
+    /// End offset of the leftmost-first match at the start of the text, as [`Regex::leftmost_prefix`] returns.
+    pub fn leftmost_prefix(&mut self, text: &[char]) -> Result<Option<usize>, MatchError> {
+        self.check_len(text.len())?;
+        let end = self
+            .regex
+            .machine
+            .run(&mut self.threads, text, Sp(0), Sp(0), Mode::First)?;
+        Ok(end.map(|end| end.0))
+    }
+
+    /// Check if the regular expression matches a suffix of the text, as [`Regex::is_suffix_match`] does.
+    pub fn is_suffix_match(&mut self, text: &str) -> Result<bool, MatchError> {
+        self.check_len(text.len())?;
+        self.chars.clear();
+        let Some(reverse_machine) = &self.regex.reverse_machine else {
+            self.chars.extend(text.chars());
+            let m = self.find_span(Sp(0), Mode::Full)?;
+            return Ok(m.is_some());
+        };
+
+        self.chars.extend(text.chars().rev());
+        // The start of the text is the end of the reversed text.
+        let search_start = Sp(self.chars.len());
+        let end = reverse_machine.run(
+            &mut self.threads,
+            &self.chars,
+            search_start,
+            Sp(0),
+            Mode::First,
+        )?;
+        Ok(end.is_some())
+    }
 }
 
-#[derive(Error, Debug)]
+#[derive(Debug)]
 pub enum SyntaxError {
-    #[error("parse error: {0}")]
-    ParseError(#[from] ParseError),
-    #[error("codegen error: {0}")]
-    GenerateCodeError(#[from] GenerateCodeError),
+    ParseError(ParseError),
+    GenerateCodeError(GenerateCodeError),
+    ValidationError(ValidationError),
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParseError(e) => write!(f, "parse error: {e}"),
+            Self::GenerateCodeError(e) => write!(f, "codegen error: {e}"),
+            Self::ValidationError(e) => write!(f, "validation error: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for SyntaxError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::ParseError(e) => Some(e),
+            Self::GenerateCodeError(e) => Some(e),
+            Self::ValidationError(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for SyntaxError {
+    fn from(e: ParseError) -> Self {
+        Self::ParseError(e)
+    }
+}
+
+impl From<GenerateCodeError> for SyntaxError {
+    fn from(e: GenerateCodeError) -> Self {
+        Self::GenerateCodeError(e)
+    }
+}
+
+impl From<ValidationError> for SyntaxError {
+    fn from(e: ValidationError) -> Self {
+        Self::ValidationError(e)
+    }
+}
+
+/// Error returned by the operations that both compile and match.
+///
+/// Both [`SyntaxError`] and [`MatchError`] convert into it, so `?` can be used across compilation and matching.
+/// The errors inside a [`SyntaxError`] convert into it too, so an error type of another crate only needs
+/// `From<Error>` to wrap them all.
+///
+/// # Example
+/// ```
+/// use vmregex::{Error, Regex};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+///     Regex(Error),
+/// }
+///
+/// impl From<Error> for AppError {
+///     fn from(e: Error) -> Self {
+///         Self::Regex(e)
+///     }
+/// }
+///
+/// fn count_matching(pattern: &str, lines: &[&str]) -> Result<usize, Error> {
+///     let re = Regex::new(pattern)?;
+///     let mut count = 0;
+///     for line in lines {
+///         if re.is_match(line)? {
+///             count += 1;
+///         }
+///     }
+///     Ok(count)
+/// }
+///
+/// fn run() -> Result<usize, AppError> {
+///     Ok(count_matching("b+", &["abc", "xyz", "bb"])?)
+/// }
+///
+/// assert_eq!(run().unwrap(), 2);
+/// assert!(matches!(
+///     count_matching("a|", &[]),
+///     Err(Error::SyntaxError(_))
+/// ));
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    SyntaxError(SyntaxError),
+    MatchError(MatchError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SyntaxError(e) => write!(f, "syntax error: {e}"),
+            Self::MatchError(e) => write!(f, "match error: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::SyntaxError(e) => Some(e),
+            Self::MatchError(e) => Some(e),
+        }
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(e: SyntaxError) -> Self {
+        Self::SyntaxError(e)
+    }
+}
+
+impl From<MatchError> for Error {
+    fn from(e: MatchError) -> Self {
+        Self::MatchError(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Self::SyntaxError(e.into())
+    }
+}
+
+impl From<GenerateCodeError> for Error {
+    fn from(e: GenerateCodeError) -> Self {
+        Self::SyntaxError(e.into())
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Self::SyntaxError(e.into())
+    }
+}
+
+/// Builder of a [`Regex`] with options.
+///
+/// # Example
+/// ```
+/// use vmregex::RegexBuilder;
+///
+/// let re = RegexBuilder::new("hello")
+///     .char_eq(|c, cc| c.eq_ignore_ascii_case(&cc))
+///     .build()
+///     .unwrap();
+/// assert!(re.is_match("HeLLo").unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    char_eq: Option<fn(char, char) -> bool>,
+    case_insensitive_classes: bool,
+    optimize: bool,
+    // Metacharacters parsed as literals.
+    literals: Vec<char>,
+    dot_matches_newline: bool,
+    max_input_len: Option<usize>,
+    reserve_capacity: bool,
+    reverse_program: bool,
+    offset_mode: OffsetMode,
+}
+
+impl RegexBuilder {
+    /// Create a builder for the pattern with the default options.
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.into(),
+            char_eq: None,
+            case_insensitive_classes: true,
+            optimize: false,
+            literals: Vec::new(),
+            dot_matches_newline: true,
+            max_input_len: None,
+            reserve_capacity: false,
+            reverse_program: false,
+            offset_mode: OffsetMode::Char,
+        }
+    }
+
+    /// Compare a literal char of the pattern (first argument) with a char of the text using `char_eq` instead of
+    /// `==`, e.g. to treat some chars as equivalent.
+    ///
+    /// `char_eq` is called through a function pointer for every literal compared, which is slower than `==`.
+    /// It does not affect `.`. By default it applies to bracket expressions too, so with a case-insensitive `char_eq`
+    /// `[a-z]` also matches `A` to `Z`: a char outside a range is compared with each char of the range, which is slow
+    /// for a large range. See [`RegexBuilder::case_insensitive_classes`] to compare bracket expressions with `==`.
+    pub fn char_eq(&mut self, char_eq: fn(char, char) -> bool) -> &mut Self {
+        self.char_eq = Some(char_eq);
+        self
+    }
+
+    /// Whether [`RegexBuilder::char_eq`] applies to the chars and ranges of bracket expressions, or only to the
+    /// literals outside them, which it usually makes case-insensitive. Enabled by default.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::RegexBuilder;
+    ///
+    /// let re = RegexBuilder::new("x[a-z]")
+    ///     .char_eq(|c, cc| c.eq_ignore_ascii_case(&cc))
+    ///     .case_insensitive_classes(false)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(re.is_match("Xa").unwrap());
+    /// assert!(!re.is_match("xA").unwrap());
+    /// ```
+    pub fn case_insensitive_classes(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive_classes = yes;
+        self
+    }
+
+    /// Optimize the program to make it smaller, at the cost of compiling more slowly. Disabled by default.
+    ///
+    /// The literal prefix shared by consecutive alternatives is matched once, e.g. `abc|abd` is compiled as
+    /// `ab(c|d)`, and likewise the shared suffix in the reversed program. The `Jmp` instructions that the program can
+    /// do without are removed, e.g. after the first alternative of `a|b`. The matches are unchanged.
+    pub fn optimize(&mut self, yes: bool) -> &mut Self {
+        self.optimize = yes;
+        self
+    }
+
+    /// Parse the given metacharacters as literals, e.g. to keep `|` for another meaning in a DSL.
+    ///
+    /// Disabling `\` disables escapes too, including `\Q...\E` and `\G`.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::RegexBuilder;
+    ///
+    /// let re = RegexBuilder::new("a|b").disable_operators(&['|']).build().unwrap();
+    /// assert!(re.is_full_match("a|b").unwrap());
+    /// assert!(!re.is_full_match("a").unwrap());
+    /// ```
+    pub fn disable_operators(&mut self, operators: &[char]) -> &mut Self {
+        self.literals = operators.to_vec();
+        self
+    }
+
+    /// Let `.` match `\n`. Enabled by default, so `.` matches any char. The inline flag `s` overrides it within its
+    /// group, e.g. `(?-s:.)` does not match `\n` and `(?s:.)` does.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::RegexBuilder;
+    ///
+    /// let re = RegexBuilder::new("a.b").dot_matches_newline(false).build().unwrap();
+    /// assert!(re.is_match("a b").unwrap());
+    /// assert!(!re.is_match("a\nb").unwrap());
+    /// ```
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.dot_matches_newline = yes;
+        self
+    }
+
+    /// Reject a text longer than `max` with [`MatchError::InputTooLong`] before reading it, e.g. to bound the time
+    /// spent on untrusted input. There is no limit by default.
+    ///
+    /// The text is not truncated, since a match in a truncated text may not be a match in the whole one. The length
+    /// is counted in bytes for a `&str` or `&[u8]`, and in chars for a `&[char]`.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{MatchError, RegexBuilder};
+    ///
+    /// let re = RegexBuilder::new("b").max_input_len(4).build().unwrap();
+    /// assert!(re.is_match("aaab").unwrap());
+    /// assert_eq!(
+    ///     re.is_match("aaaab"),
+    ///     Err(MatchError::InputTooLong { len: 5, max: 4 })
+    /// );
+    /// ```
+    pub fn max_input_len(&mut self, max: usize) -> &mut Self {
+        self.max_input_len = Some(max);
+        self
+    }
+
+    /// Reserve room for as many backtracking threads as there are instructions when a [`Matcher`] is created, so
+    /// that the stack of a large program is not reallocated as it grows. Disabled by default, since one-shot methods
+    /// such as [`Regex::is_match`] create a [`Matcher`] each time, even for a text that is rejected without running
+    /// the program.
+    pub fn reserve_capacity(&mut self, yes: bool) -> &mut Self {
+        self.reserve_capacity = yes;
+        self
+    }
+
+    /// Also compile the reversed pattern, which [`Regex::is_suffix_match`] runs backward from the end of the text, so
+    /// that e.g. `.*txt` rejects a text without scanning it from every offset. Disabled by default, since it doubles
+    /// the time to compile and the size of the programs for a regex that may never look for a suffix.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::RegexBuilder;
+    ///
+    /// let re = RegexBuilder::new(".*txt").reverse_program(true).build().unwrap();
+    /// assert!(!re.is_suffix_match("data.txt.bak").unwrap());
+    /// ```
+    pub fn reverse_program(&mut self, yes: bool) -> &mut Self {
+        self.reverse_program = yes;
+        self
+    }
+
+    /// Count the offsets into a `&str` in chars or in bytes. [`OffsetMode::Char`] by default, as the text is matched
+    /// as a sequence of chars.
+    ///
+    /// The mode applies to [`Match::start`] and [`Match::end`] of every method returning a [`Match`], to the `start`
+    /// of [`Regex::find_at`], which must then be on a char boundary, and to [`Regex::consumed_prefix_len`]. Methods
+    /// over a `&[char]` count chars, and those named after bytes count bytes, whatever the mode.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{OffsetMode, RegexBuilder};
+    ///
+    /// let re = RegexBuilder::new("b+").offset_mode(OffsetMode::Byte).build().unwrap();
+    /// let m = re.find("äöbb").unwrap().unwrap();
+    /// assert_eq!((m.start(), m.end()), (4, 6));
+    /// ```
+    pub fn offset_mode(&mut self, mode: OffsetMode) -> &mut Self {
+        self.offset_mode = mode;
+        self
+    }
+
+    /// Compile the regular expression.
+    pub fn build(&self) -> Result<Regex, SyntaxError> {
+        self.compile(self.parse()?)
+    }
+
+    /// Parse the pattern into the AST that the program is generated from.
+    fn parse(&self) -> Result<Ast, SyntaxError> {
+        // Without `char_eq`, every char is compared with `==` anyway.
+        let exact_classes = self.char_eq.is_some() && !self.case_insensitive_classes;
+        let ast = parser::parse_with_literals(&self.pattern, &self.literals, exact_classes)?;
+        Ok(optimizer::simplify(ast))
+    }
+
+    /// Generate the programs from the AST returned by [`RegexBuilder::parse`].
+    fn compile(&self, ast: Ast) -> Result<Regex, SyntaxError> {
+        let min_len = ast.min_len();
+        let max_len = ast.max_len();
+        let anchored_start = ast.is_anchored_start();
+        // With `char_eq`, a literal may match other chars than itself.
+        let (required_chars, required_literals) = if self.char_eq.is_none() {
+            (ast.required_chars(), ast.required_literals())
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let mut reversible = self.reverse_program;
+        if reversible {
+            ast.walk(&mut |ast| {
+                if matches!(ast, Ast::LookAhead(_) | Ast::NegativeLookAhead(_)) {
+                    reversible = false;
+                }
+            });
+        }
+        let reverse_ast = reversible.then(|| ast.clone().reverse());
+
+        let compile = |ast| -> Result<Machine, SyntaxError> {
+            let ast = if self.optimize {
+                optimizer::factor_prefixes(ast)
+            } else {
+                ast
+            };
+            let mut instructions =
+                codegen::generate_code(ast, self.dot_matches_newline, self.char_eq.is_none())?;
+            if self.optimize {
+                instructions = codegen::remove_jumps(instructions);
+            }
+            let mut machine = Machine::new(instructions);
+            // Sanity check of the code generator.
+            machine.validate()?;
+            if let Some(char_eq) = self.char_eq {
+                machine = machine.with_char_eq(char_eq);
+                if self.case_insensitive_classes {
+                    machine = machine.with_ranges_char_eq();
+                }
+            }
+            Ok(machine)
+        };
+        let machine = compile(ast.clone())?;
+        let reverse_machine = reverse_ast.map(compile).transpose()?;
+        let threads_capacity = if self.reserve_capacity {
+            machine.instructions().len()
+        } else {
+            0
+        };
+        Ok(Regex {
+            machine,
+            reverse_machine,
+            min_len,
+            max_len,
+            anchored_start,
+            required_chars,
+            required_literals,
+            ast,
+            dot_matches_newline: self.dot_matches_newline,
+            max_input_len: self.max_input_len,
+            threads_capacity,
+            offset_mode: self.offset_mode,
+            hex: false,
+        })
+    }
+}
+
+/// Regular expression compiled by [`Regex::compile_stages`], together with the AST it was compiled from.
+pub struct CompileReport {
+    /// AST parsed from the pattern and simplified, from which the program is generated.
+    pub ast: Ast,
+    pub regex: Regex,
 }
 
 impl Regex {
     /// Compile a regular expression.
+    ///
+    /// An empty pattern is valid and matches the empty string, i.e. at every position of any text.
+    /// Use [`RegexBuilder`] to compile it with options.
     pub fn new(pattern: &str) -> Result<Self, SyntaxError> {
-        let ast = parser::parse(pattern)?;
-        let instructions = codegen::generate_code(ast)?;
-        let machine = Machine::new(instructions);
-        Ok(Self { machine })
+        RegexBuilder::new(pattern).build()
+    }
+
+    /// Compile a regular expression over bytes, written as pairs of hex digits, to match binary data with
+    /// [`Regex::is_match_bytes`].
+    ///
+    /// Every byte is two hex digits, and whitespace between the bytes is skipped. Operators, lookarounds, bracket
+    /// expressions such as `[00-1f]` and `\G` work as in [`Regex::new`], and `.` matches any byte. A byte is matched
+    /// as the char of the same value, from U+0000 to U+00FF, so the program is the one of an equivalent char pattern.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new_hex("ff d8 ff (e0|e1)").unwrap();
+    /// assert!(re.is_match_bytes(&[0xff, 0xd8, 0xff, 0xe1, 0x00]).unwrap());
+    /// assert!(!re.is_match_bytes(&[0xff, 0xd8, 0xff, 0xdb]).unwrap());
+    /// ```
+    pub fn new_hex(pattern: &str) -> Result<Self, SyntaxError> {
+        let mut re = Self::new(&parser::hex_pattern(pattern)?)?;
+        re.hex = true;
+        Ok(re)
+    }
+
+    /// Compile a regular expression as [`Regex::new`] does, and also return the intermediate AST.
+    ///
+    /// On failure, the variant of the [`SyntaxError`] tells the stage that failed: [`SyntaxError::ParseError`] for
+    /// parsing, and [`SyntaxError::GenerateCodeError`] or [`SyntaxError::ValidationError`] for code generation.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{Ast, ParseError, Regex, SyntaxError};
+    ///
+    /// let report = Regex::compile_stages("a(b)").unwrap();
+    /// assert_eq!(report.ast, Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]));
+    /// assert!(report.regex.is_match("ab").unwrap());
+    ///
+    /// assert!(matches!(
+    ///     Regex::compile_stages("a|"),
+    ///     Err(SyntaxError::ParseError(ParseError::MissingOperand))
+    /// ));
+    /// ```
+    pub fn compile_stages(pattern: &str) -> Result<CompileReport, SyntaxError> {
+        let builder = RegexBuilder::new(pattern);
+        let ast = builder.parse()?;
+        let regex = builder.compile(ast.clone())?;
+        Ok(CompileReport { ast, regex })
+    }
+
+    /// Minimum length of a match in chars.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert_eq!(Regex::new("a?b").unwrap().min_len(), 1);
+    /// ```
+    pub fn min_len(&self) -> usize {
+        self.min_len
+    }
+
+    /// Maximum length of a match in chars, or `None` if a quantifier such as `*` or `+` makes it unbounded.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert_eq!(Regex::new("a?b").unwrap().max_len(), Some(2));
+    /// assert_eq!(Regex::new("a*").unwrap().max_len(), None);
+    /// ```
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Check if every match starts where the search begins, because every alternative of the pattern begins with
+    /// `\G`. Then [`Regex::is_match`] only tries the start of the text, as [`Regex::is_prefix_match`] does.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert!(Regex::new("\\Gab|\\Gc").unwrap().is_anchored_start());
+    /// assert!(!Regex::new("\\Gab|c").unwrap().is_anchored_start());
+    /// ```
+    pub fn is_anchored_start(&self) -> bool {
+        self.anchored_start
+    }
+
+    /// Whether `.` matches `\n`, i.e. the program consumes a char with [`Instruction::AnyByte`] rather than
+    /// [`Instruction::AnyByteExceptNewline`]. See [`RegexBuilder::dot_matches_newline`].
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{Regex, RegexBuilder};
+    ///
+    /// assert!(Regex::new("a.b").unwrap().dot_matches_newline());
+    /// let re = RegexBuilder::new("a.b").dot_matches_newline(false).build().unwrap();
+    /// assert!(!re.dot_matches_newline());
+    /// ```
+    pub fn dot_matches_newline(&self) -> bool {
+        self.dot_matches_newline
+    }
+
+    /// Unit of the offsets into a text. See [`RegexBuilder::offset_mode`].
+    pub fn offset_mode(&self) -> OffsetMode {
+        self.offset_mode
+    }
+
+    /// Compiled program, to run it on another virtual machine. See [`Instruction`] for the semantics.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{Instruction, Pc, Regex};
+    ///
+    /// let re = Regex::new("ab+").unwrap();
+    /// assert_eq!(
+    ///     re.instructions(),
+    ///     [
+    ///         Instruction::Char('a'),
+    ///         Instruction::Char('b'),
+    ///         Instruction::Split(Pc(1), Pc(3)),
+    ///         Instruction::Match,
+    ///     ]
+    /// );
+    /// ```
+    pub fn instructions(&self) -> &[Instruction] {
+        self.machine.instructions()
+    }
+
+    /// PCs of the instructions of the compiled program that no thread can reach, which an optimization pass should
+    /// not leave behind.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert!(Regex::new("a|b*c").unwrap().unreachable_instructions().is_empty());
+    /// ```
+    pub fn unreachable_instructions(&self) -> Vec<Pc> {
+        self.machine.unreachable_instructions()
+    }
+
+    /// Key that is the same for patterns that differ only in redundant constructs, such as groups, empty groups and
+    /// repeated alternatives, e.g. `ab`, `(ab)` and `a()b`, in order to deduplicate patterns.
+    ///
+    /// Equal keys mean that the patterns match the same, but some equivalent patterns such as `a|b` and `b|a` have
+    /// different keys. The options of [`RegexBuilder`] are not part of the key, so only compare the keys of regular
+    /// expressions built with the same options. The key is built on each call, from the simplified AST.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let key = |pattern| Regex::new(pattern).unwrap().canonical_key();
+    /// assert_eq!(key("(ab)c"), key("a(bc)"));
+    /// assert_ne!(key("abc"), key("acb"));
+    /// ```
+    pub fn canonical_key(&self) -> String {
+        self.ast.key()
+    }
+
+    /// Runs of literal chars that every match contains, in the order of the pattern, e.g. to look up the texts that
+    /// may match in an index of substrings before matching them.
+    ///
+    /// An alternation only requires the literals common to all its operands, and an optional part requires none.
+    /// There are none with [`RegexBuilder::char_eq`], since a literal may then match other chars.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert_eq!(Regex::new("foo.*bar").unwrap().required_literals(), ["foo", "bar"]);
+    /// assert_eq!(Regex::new("(foo|bar)baz").unwrap().required_literals(), ["baz"]);
+    /// ```
+    pub fn required_literals(&self) -> &[String] {
+        &self.required_literals
+    }
+
+    /// Check if two operands of an alternation in the pattern can both match at the same position, so that their
+    /// order decides which one is matched, e.g. in a lexer where `abc|ab` and `ab|abc` find different tokens.
+    ///
+    /// The operands overlap if they can start with the same char or one of them can match the empty string, unless
+    /// both match exactly one char, as in a class such as `[a-cb]`. Chars are compared with `==`, even with
+    /// [`RegexBuilder::char_eq`]. The AST is searched on each call.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// assert!(Regex::new("(abc|ab)").unwrap().alternation_overlaps());
+    /// assert!(!Regex::new("(a|b)").unwrap().alternation_overlaps());
+    /// ```
+    pub fn alternation_overlaps(&self) -> bool {
+        self.ast.has_overlapping_alternation()
+    }
+
+    /// Check if the bytes match the regular expression, which must be compiled with [`Regex::new_hex`].
+    ///
+    /// Every byte is matched as the char of the same value, and the match can start anywhere as in
+    /// [`Regex::is_match`]. Begin the pattern with `\G` to match only at the start, e.g. for a file signature.
+    ///
+    /// A regex compiled from chars fails with [`MatchError::NotHexPattern`], since its chars are not bytes: `é` would
+    /// match the byte `0xE9` rather than its UTF-8 encoding. Match the text decoded as a `&str` instead.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{MatchError, Regex};
+    ///
+    /// assert!(Regex::new_hex("c3 a9").unwrap().is_match_bytes("é".as_bytes()).unwrap());
+    /// assert_eq!(
+    ///     Regex::new("é").unwrap().is_match_bytes("é".as_bytes()),
+    ///     Err(MatchError::NotHexPattern)
+    /// );
+    /// ```
+    pub fn is_match_bytes(&self, bytes: &[u8]) -> Result<bool, MatchError> {
+        if !self.hex {
+            return Err(MatchError::NotHexPattern);
+        }
+        let mut matcher = self.matcher();
+        matcher.check_len(bytes.len())?;
+        matcher.chars.extend(bytes.iter().map(|&b| char::from(b)));
+        Ok(matcher.search_at(Sp(0))?.is_some())
+    }
+
+    /// Check the length of the text against [`RegexBuilder::max_input_len`] before reading it.
+    fn check_len(&self, len: usize) -> Result<(), MatchError> {
+        match self.max_input_len {
+            Some(max) if len > max => Err(MatchError::InputTooLong { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if the regular expression matches somewhere in the text as [`Regex::is_match`] does, but reject a text
+    /// that cannot match before any work on the machine, e.g. to scan a log where most lines do not match.
+    ///
+    /// A text is rejected without creating a [`Matcher`], which allocates, if it is shorter than any match or lacks
+    /// one of the [`Regex::required_literals`] or of the chars that every match contains. Looking for the literals
+    /// costs a scan of the text each, which [`Regex::is_match`] does not spend on a text likely to match.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("ERROR.*timeout").unwrap();
+    /// assert!(re.any_match_in("12:00 ERROR db timeout").unwrap());
+    /// assert!(!re.any_match_in("12:00 INFO db timeout").unwrap());
+    /// ```
+    pub fn any_match_in(&self, text: &str) -> Result<bool, MatchError> {
+        self.check_len(text.len())?;
+        // `len` counts bytes, which is not less than the chars.
+        if text.len() < self.min_len
+            || !self.required_chars.iter().all(|&c| text.contains(c))
+            || !self
+                .required_literals
+                .iter()
+                .all(|literal| text.contains(literal.as_str()))
+        {
+            return Ok(false);
+        }
+        self.is_match(text)
+    }
+
+    /// Create a [`Matcher`], which reuses its buffers to match this regular expression against many texts.
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher {
+            regex: self,
+            chars: Vec::new(),
+            offsets: Vec::new(),
+            threads: Threads::with_capacity(self.threads_capacity),
+        }
+    }
+
+    /// Check if the regular expression matches somewhere in the text.
+    ///
+    /// The text is anything that can be borrowed as a `&str`, such as a `String` by value. Only this method is generic
+    /// over the text, and the other methods of [`Regex`] and [`Matcher`] take a `&str`.
+    /// Use [`Regex::is_prefix_match`] for a match at the start of the text, and [`Regex::is_full_match`] for a match
+    /// of the whole text.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("bc").unwrap();
+    /// assert!(re.is_match("abcd").unwrap());
+    /// assert!(re.is_match(String::from("abcd")).unwrap());
+    /// assert!(!re.is_prefix_match("abcd").unwrap());
+    /// assert!(!re.is_full_match("abcd").unwrap());
+    /// ```
+    pub fn is_match<T: AsRef<str>>(&self, text: T) -> Result<bool, MatchError> {
+        self.matcher().is_match(text.as_ref())
+    }
+
+    /// Check if the regular expression matches somewhere in the text as [`Regex::is_match`] does, and count the
+    /// instructions executed, the splits tried and the deepest backtracking stack, e.g. to find out why a pattern is
+    /// slow.
+    ///
+    /// Every start of a match is tried, even when [`Regex::is_match`] could tell without running the program that
+    /// there is no match, so the counts are those of the whole search.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let (matched, stats) = Regex::new("a*b").unwrap().is_match_with_stats("aaab").unwrap();
+    /// assert!(matched);
+    /// assert_eq!(stats.split_count, 4);
+    /// ```
+    pub fn is_match_with_stats(&self, text: &str) -> Result<(bool, MatchStats), MatchError> {
+        self.matcher().is_match_with_stats(text)
+    }
+
+    /// Check if the regular expression matches at the start of the text, where the match may end anywhere.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("ab").unwrap();
+    /// assert!(re.is_prefix_match("abc").unwrap());
+    /// assert!(!re.is_prefix_match("cab").unwrap());
+    /// ```
+    pub fn is_prefix_match(&self, text: &str) -> Result<bool, MatchError> {
+        self.matcher().is_prefix_match(text)
+    }
+
+    /// Check if each of the texts matches the regular expression, as [`Regex::is_match`] does.
+    ///
+    /// The buffers used while matching are reused across the texts, rather than allocated for each, as with a
+    /// [`Matcher`].
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("ab+").unwrap();
+    /// assert_eq!(
+    ///     re.is_match_many(["abb", "ba", "ab"]).unwrap(),
+    ///     [true, false, true]
+    /// );
+    /// ```
+    pub fn is_match_many<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        texts: I,
+    ) -> Result<Vec<bool>, MatchError> {
+        let mut matcher = self.matcher();
+        texts
+            .into_iter()
+            .map(|text| matcher.is_match(text))
+            .collect()
+    }
+
+    /// Check if the regular expression matches starting exactly at the byte offset of the text, e.g. a position
+    /// kept by an editor.
+    ///
+    /// The chars before the offset are still seen by a lookbehind, and `\G` asserts the offset, as in
+    /// [`Regex::find_at`]. An offset that is not on a char boundary, or is past the end of the text, is an error
+    /// rather than a panic.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{MatchError, Regex};
+    ///
+    /// let re = Regex::new("b+").unwrap();
+    /// assert!(re.is_match_at_byte("ébb", 2).unwrap());
+    /// assert!(!re.is_match_at_byte("ébb", 0).unwrap());
+    /// assert_eq!(
+    ///     re.is_match_at_byte("ébb", 1),
+    ///     Err(MatchError::NotCharBoundary { offset: 1 })
+    /// );
+    /// ```
+    pub fn is_match_at_byte(&self, text: &str, offset: usize) -> Result<bool, MatchError> {
+        self.matcher().is_match_at_byte(text, offset)
+    }
+
+    /// Check if the whole text matches the regular expression, as if it were anchored at both ends.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("(a|b)+").unwrap();
+    /// assert!(re.is_full_match("abba").unwrap());
+    /// assert!(!re.is_full_match("abc").unwrap());
+    /// ```
+    pub fn is_full_match(&self, text: &str) -> Result<bool, MatchError> {
+        self.matcher().is_full_match(text)
+    }
+
+    /// Check if the whole line matches the regular expression, as [`Regex::is_full_match`] does for the line without
+    /// its terminator, `\n` or `\r\n`, if any.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("ERROR.*").unwrap();
+    /// assert!(re.full_line_match("ERROR disk full\r\n").unwrap());
+    /// assert!(!re.full_line_match("warn: ERROR\n").unwrap());
+    /// ```
+    pub fn full_line_match(&self, line: &str) -> Result<bool, MatchError> {
+        let line = line
+            .strip_suffix("\r\n")
+            .or_else(|| line.strip_suffix('\n'))
+            .unwrap_or(line);
+        self.is_full_match(line)
+    }
+
+    /// Find the leftmost match in the text.
+    ///
+    /// Among the matches starting at the leftmost position, the one found first is returned, i.e. alternatives and
+    /// quantifiers are tried in order (leftmost-first, as in Perl).
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("a|ab").unwrap();
+    /// let m = re.find("xxab").unwrap().unwrap();
+    /// assert_eq!((m.start(), m.end(), m.as_str()), (2, 3, "a"));
+    /// ```
+    pub fn find<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, MatchError> {
+        self.matcher().find(text)
+    }
+
+    /// Find the leftmost match in the text that starts at or after the offset `start`, counted in chars unless
+    /// [`RegexBuilder::offset_mode`] is [`OffsetMode::Byte`]. A byte offset inside a char is an error
+    /// [`MatchError::NotCharBoundary`].
+    ///
+    /// Unlike slicing the text, `\G` in the pattern asserts the position `start`, so a tokenizer can require every
+    /// token to begin right where the previous one ended. `\G` is only meaningful with this method and
+    /// [`Regex::find_iter`]; other methods treat it as the start of the text.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("\\Gab").unwrap();
+    /// assert_eq!(re.find_at("xabab", 1).unwrap().unwrap().end(), 3);
+    /// assert!(re.find_at("xabab", 0).unwrap().is_none());
+    /// ```
+    pub fn find_at<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+    ) -> Result<Option<Match<'t>>, MatchError> {
+        self.matcher().find_at(text, start)
+    }
+
+    /// Iterate over the successive non-overlapping leftmost-first matches in the text.
+    ///
+    /// Empty matches are handled as in the `regex` crate: after an empty match, the next search starts one char
+    /// further, and an empty match that begins right where the previous match ended is not reported.
+    /// The iterator stops after yielding an error.
+    ///
+    /// The text is split into chars once, and each search resumes over the same chars where the previous one ended, so
+    /// iterating over all the matches does not copy the text again.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("a*").unwrap();
+    /// let spans = re
+    ///     .find_iter("baaab")
+    ///     .map(|m| m.map(|m| (m.start(), m.end())))
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(spans, [(0, 0), (1, 4), (5, 5)]);
+    /// ```
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        let mut matcher = self.matcher();
+        let error = matcher.check_len(text.len()).err();
+        if error.is_none() {
+            matcher.load(text);
+        }
+        Matches {
+            matcher,
+            text,
+            start: Some(Sp(0)),
+            last_end: None,
+            error,
+        }
+    }
+
+    /// Split the text at the matches of [`Regex::find_iter`], returning the parts between them, which borrow the text.
+    ///
+    /// There is one more part than matches, so a match at either end of the text gives an empty part there.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new(", *").unwrap();
+    /// assert_eq!(re.split("a, b,c,").unwrap(), ["a", "b", "c", ""]);
+    /// ```
+    pub fn split<'t>(&self, text: &'t str) -> Result<Vec<&'t str>, MatchError> {
+        let mut parts = Vec::new();
+        let mut last = 0;
+        for m in self.find_iter(text) {
+            let m = m?;
+            parts.push(&text[last..m.byte_start]);
+            last = m.byte_end;
+        }
+        parts.push(&text[last..]);
+        Ok(parts)
+    }
+
+    /// Find the leftmost-longest match in the text, as in POSIX.
+    ///
+    /// Among the matches starting at the leftmost position, the longest one is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("a|ab").unwrap();
+    /// let m = re.longest_match("xxab").unwrap().unwrap();
+    /// assert_eq!((m.start(), m.end(), m.as_str()), (2, 4, "ab"));
+    /// ```
+    pub fn longest_match<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, MatchError> {
+        self.matcher().longest_match(text)
+    }
+
+    /// Length in chars of the longest match at the start of the text, or `None` if no match starts there.
+    ///
+    /// The match need not reach the end of the text, so this tells how far to advance a cursor over the chars.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("a+").unwrap();
+    /// let text = "aaab".chars().collect::<Vec<_>>();
+    /// assert_eq!(re.match_prefix_len(&text).unwrap(), Some(3));
+    /// assert_eq!(re.match_prefix_len(&text[3..]).unwrap(), None);
+    /// ```
+    pub fn match_prefix_len(&self, text: &[char]) -> Result<Option<usize>, MatchError> {
+        self.matcher().match_prefix_len(text)
+    }
+
+    /// End offset of the leftmost-first match at the start of the text, or `None` if no match starts there.
+    ///
+    /// This is [`Regex::is_prefix_match`] and the length of the match in a single run, e.g. for a lexer. Unlike
+    /// [`Regex::match_prefix_len`], alternatives and quantifiers are tried in order, as in [`Regex::find`].
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("(0|1|2|3|4|5|6|7|8|9)+").unwrap();
+    /// let text = "123abc".chars().collect::<Vec<_>>();
+    /// assert_eq!(re.leftmost_prefix(&text).unwrap(), Some(3));
+    /// ```
+    pub fn leftmost_prefix(&self, text: &[char]) -> Result<Option<usize>, MatchError> {
+        self.matcher().leftmost_prefix(text)
+    }
+
+    /// Length of the longest prefix of the text that the regular expression consumes when matched at the start of the
+    /// text, whether it matches or not, e.g. to tell where a failed match got stuck. It is counted in chars unless
+    /// [`RegexBuilder::offset_mode`] is [`OffsetMode::Byte`].
+    ///
+    /// Every alternative is tried to its end, so this is slower than [`Regex::is_prefix_match`]. The chars looked at
+    /// by a lookaround do not count as consumed.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("abcd").unwrap();
+    /// assert!(!re.is_prefix_match("abcX").unwrap());
+    /// assert_eq!(re.consumed_prefix_len("abcX").unwrap(), 3);
+    /// ```
+    pub fn consumed_prefix_len(&self, text: &str) -> Result<usize, MatchError> {
+        self.matcher().consumed_prefix_len(text)
+    }
+
+    /// Replace every match in the text with the template `rep`.
+    ///
+    /// The matches are those of [`Regex::find_iter`], so an empty match is replaced except right after another
+    /// match, as in the `regex` crate. Every char of the text outside the matches is kept exactly once.
+    ///
+    /// In the template, `$N` or `${N}` is replaced with group `N`, and `$$` with a literal `$`. A group name after `$`
+    /// is the longest run of `[A-Za-z0-9_]`, so `$1a` is the group `1a`; write `${1}a` for group 1 followed by `a`.
+    /// The only group is 0, the whole match, and any other group is replaced with nothing. A `$` that does not start
+    /// a group, such as one at the end or in an unterminated `${`, is inserted literally.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("a*").unwrap();
+    /// assert_eq!(re.replace_all("aba", "X").unwrap(), "XbX");
+    /// assert_eq!(re.replace_all("bcb", "X").unwrap(), "XbXcXbX");
+    ///
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// assert_eq!(re.replace_all("a1b22", "<$0>").unwrap(), "a<1>b<22>");
+    /// assert_eq!(re.replace_all("1 kg", "${0}0$$").unwrap(), "10$ kg");
+    /// ```
+    pub fn replace_all(&self, text: &str, rep: &str) -> Result<String, MatchError> {
+        let mut out = String::with_capacity(text.len());
+        self.replace_all_into(text, rep, &mut out)?;
+        Ok(out)
+    }
+
+    /// Replace every match in the text with `rep`, writing the result into `out`.
+    ///
+    /// `out` is cleared first, so the same buffer can be reused across calls without allocating a new `String`.
+    /// `rep` is a template as in [`Regex::replace_all`].
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("o+").unwrap();
+    /// let mut out = String::new();
+    /// re.replace_all_into("foo boo", "0", &mut out).unwrap();
+    /// assert_eq!(out, "f0 b0");
+    /// ```
+    pub fn replace_all_into(
+        &self,
+        text: &str,
+        rep: &str,
+        out: &mut String,
+    ) -> Result<(), MatchError> {
+        out.clear();
+
+        let mut last = 0;
+        for m in self.find_iter(text) {
+            let m = m?;
+            out.push_str(&text[last..m.byte_start]);
+            expand(rep, m.as_str(), out);
+            last = m.byte_end;
+        }
+        out.push_str(&text[last..]);
+
+        Ok(())
+    }
+
+    /// Replace the first match in the text with the output of `f`, which is given the matched text.
+    ///
+    /// The text is returned unchanged if there is no match.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new("o+").unwrap();
+    /// let out = re.replace_with("foo boo", |m| m.to_uppercase()).unwrap();
+    /// assert_eq!(out, "fOO boo");
+    /// ```
+    pub fn replace_with<F>(&self, text: &str, mut f: F) -> Result<String, MatchError>
+    where
+        F: FnMut(&str) -> String,
+    {
+        let Some(m) = self.find(text)? else {
+            return Ok(text.into());
+        };
+
+        let mut out = String::with_capacity(text.len());
+        out.push_str(&text[..m.byte_start]);
+        out.push_str(&f(m.as_str()));
+        out.push_str(&text[m.byte_end..]);
+        Ok(out)
+    }
+
+    /// Check if the regular expression matches a suffix of the text, i.e. a match that ends at the end of the text.
+    ///
+    /// The pattern is tried at every offset, unless it is compiled with [`RegexBuilder::reverse_program`]: the reversed
+    /// program is then run backward from the end of the text, so patterns ending in a literal such as `.*txt` reject a
+    /// non-matching text without scanning it from every offset. A pattern with a lookahead has no reversed program.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::Regex;
+    ///
+    /// let re = Regex::new(".+txt").unwrap();
+    /// assert!(re.is_suffix_match("data.txt").unwrap());
+    /// assert!(!re.is_suffix_match("data.txt.bak").unwrap());
+    /// ```
+    pub fn is_suffix_match(&self, text: &str) -> Result<bool, MatchError> {
+        self.matcher().is_suffix_match(text)
+    }
+}
+
+impl core::str::FromStr for Regex {
+    type Err = SyntaxError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::new(pattern)
+    }
+}
+
+impl TryFrom<&str> for Regex {
+    type Error = SyntaxError;
+
+    fn try_from(pattern: &str) -> Result<Self, Self::Error> {
+        Self::new(pattern)
+    }
+}
+
+/// Compile a regular expression and check if the text matches it.
+///
+/// # Example
+/// ```
+/// assert!(vmregex::is_match("a+", "aaa").unwrap());
+/// assert!(!vmregex::is_match("a+", "bbb").unwrap());
+/// ```
+pub fn is_match(pattern: &str, text: &str) -> Result<bool, Error> {
+    let re = Regex::new(pattern)?;
+    Ok(re.is_match(text)?)
+}
+
+/// Append the replacement template to `out`, with group 0 being `matched`.
+fn expand(template: &str, matched: &str, out: &mut String) {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            }
+        } else if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        } else {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if name.is_empty() {
+            // Not a group, so the `$` is literal and the rest is read again.
+            out.push('$');
+            continue;
+        }
+        if name.parse() == Ok(0usize) {
+            out.push_str(matched);
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+}
+
+#[cfg(test)]
+// Texts are passed by reference as in the callers written before `is_match` was generic.
+#[allow(clippy::needless_borrows_for_generic_args)]
+mod test {
+    use alloc::{format, vec};
+
+    use super::*;
+
+    fn compile_and_match(pattern: &str, text: &str) -> Result<bool, Error> {
+        let re = Regex::new(pattern)?;
+        let matched = re.is_match(text)?;
+        Ok(matched)
+    }
+
+    #[test]
+    fn dot() {
+        let re = Regex::new("R.+st").unwrap();
+        assert!(re.is_match("Rxxxst").unwrap());
+        assert!(re.is_match("R.st").unwrap());
+        assert!(!re.is_match("Rst").unwrap());
+    }
+
+    #[test]
+    fn find() {
+        let re = Regex::new("a|ab").unwrap();
+        assert_eq!(re.find("ab").unwrap().unwrap().as_str(), "a");
+        assert_eq!(re.longest_match("ab").unwrap().unwrap().as_str(), "ab");
+        assert_eq!(re.find("xyz").unwrap(), None);
+        assert_eq!(re.longest_match("xyz").unwrap(), None);
+
+        // The match starting leftmost is found, even if a later one is longer.
+        let re = Regex::new("a+b").unwrap();
+        for m in [re.find("xxabxxaab"), re.longest_match("xxabxxaab")] {
+            let m = m.unwrap().unwrap();
+            assert_eq!((m.start(), m.end(), m.as_str()), (2, 4, "ab"));
+        }
+        let re = Regex::new("b|ab").unwrap();
+        assert_eq!(re.find("xab").unwrap().unwrap().as_str(), "ab");
+
+        // Offsets are counted in chars.
+        let re = Regex::new("b+").unwrap();
+        let m = re.find("äöbb").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (2, 4, "bb"));
+
+        let re = Regex::new("x*").unwrap();
+        let m = re.find("ab").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (0, 0, ""));
+
+        // A pattern anchored with `\G` is only tried at the start of the search.
+        let re = Regex::new("\\Gabc").unwrap();
+        assert_eq!(re.find("xabc").unwrap(), None);
+        assert_eq!(re.longest_match("xabc").unwrap(), None);
+        assert_eq!(re.find_at("xabc", 1).unwrap().unwrap().start(), 1);
+        assert_eq!(re.find_at("xabc", 5).unwrap(), None);
+        let m = Regex::new("abc").unwrap().find("xabc").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (1, 4, "abc"));
+    }
+
+    #[test]
+    fn anchoring() {
+        let modes = |pattern, text| {
+            let re = Regex::new(pattern).unwrap();
+            let mut matcher = re.matcher();
+            let modes = (
+                re.is_match(text).unwrap(),
+                re.is_prefix_match(text).unwrap(),
+                re.is_full_match(text).unwrap(),
+            );
+            assert_eq!(matcher.is_match(text).unwrap(), modes.0);
+            assert_eq!(matcher.is_prefix_match(text).unwrap(), modes.1);
+            assert_eq!(matcher.is_full_match(text).unwrap(), modes.2);
+            modes
+        };
+        assert_eq!(modes("ab", "abc"), (true, true, false));
+        assert_eq!(modes("bc", "abc"), (true, false, false));
+        assert_eq!(modes("abc", "abc"), (true, true, true));
+        assert_eq!(modes("ac", "abc"), (false, false, false));
+        assert_eq!(modes("\\Gbc", "abc"), (false, false, false));
+        assert_eq!(modes("", "abc"), (true, true, false));
+
+        // One compiled pattern answers every mode, since the unanchored search tries each start at match time.
+        let re = Regex::new("abc").unwrap();
+        assert!(re.is_full_match("abc").unwrap());
+        assert!(re.is_match("abc").unwrap());
+        assert!(!re.is_full_match("xabcx").unwrap());
+        assert!(re.is_match("xabcx").unwrap());
+    }
+
+    #[test]
+    fn is_match_at_byte() {
+        let re = Regex::new("b+").unwrap();
+        // `é` is 2 bytes and `日` is 3 bytes long.
+        let text = "éb日bb";
+        assert!(re.is_match_at_byte(text, 2).unwrap());
+        assert!(re.is_match_at_byte(text, 6).unwrap());
+        assert!(re.is_match_at_byte(text, 7).unwrap());
+        assert!(!re.is_match_at_byte(text, 0).unwrap());
+        assert!(!re.is_match_at_byte(text, 3).unwrap());
+        assert!(!re.is_match_at_byte(text, 8).unwrap());
+        for offset in [1, 4, 5, 9] {
+            assert_eq!(
+                re.is_match_at_byte(text, offset),
+                Err(MatchError::NotCharBoundary { offset })
+            );
+        }
+
+        // A lookbehind sees the chars before the offset, and `\G` asserts the offset.
+        let re = Regex::new("(?<=日)b").unwrap();
+        assert!(re.is_match_at_byte(text, 6).unwrap());
+        assert!(!re.is_match_at_byte(text, 7).unwrap());
+        let re = Regex::new("\\Gb").unwrap();
+        assert!(re.is_match_at_byte(text, 7).unwrap());
+        assert!(re.matcher().is_match_at_byte(text, 2).unwrap());
+    }
+
+    #[test]
+    fn group_quantifier() {
+        let re = Regex::new("(ab)*c").unwrap();
+        assert!(re.is_full_match("ababc").unwrap());
+        assert!(!re.is_full_match("abbc").unwrap());
+
+        let re = Regex::new("((ab)c)?d").unwrap();
+        assert!(re.is_full_match("abcd").unwrap());
+        assert!(re.is_full_match("d").unwrap());
+        assert!(!re.is_full_match("abd").unwrap());
+        assert!(!re.is_full_match("cd").unwrap());
+    }
+
+    #[test]
+    fn is_match_many() {
+        let re = Regex::new("a(b|c)+").unwrap();
+        let texts = ["abc", "", "a", "acb", "xab", "abbbbbbbbbbbbbbbbbbbbb"];
+        let matched = re.is_match_many(texts).unwrap();
+        assert_eq!(matched, [true, false, false, true, true, true]);
+        for (text, matched) in texts.iter().zip(matched) {
+            assert_eq!(re.is_match(text).unwrap(), matched);
+        }
+        assert!(re.is_match_many([]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn is_match_with_stats() {
+        let text = "a".repeat(12);
+        let (matched, nested) = Regex::new("(a+a+)+b")
+            .unwrap()
+            .is_match_with_stats(&text)
+            .unwrap();
+        assert!(!matched);
+        let (matched, literal) = Regex::new("aaab")
+            .unwrap()
+            .is_match_with_stats(&text)
+            .unwrap();
+        assert!(!matched);
+        assert_eq!(literal.split_count, 0);
+        assert_eq!(literal.max_depth, 0);
+        assert!(nested.split_count > 1000);
+        assert!(nested.instruction_count > literal.instruction_count * 100);
+        assert!(nested.max_depth > 0);
+
+        // The stats are those of the run that `is_match` would do.
+        let re = Regex::new("\\Ga*b").unwrap();
+        let (matched, stats) = re.is_match_with_stats("aab").unwrap();
+        assert!(matched);
+        // Every `a` consumed leaves a thread behind.
+        assert_eq!(stats.split_count, 3);
+        assert_eq!(stats.max_depth, 3);
+        assert!(!re.is_match_with_stats("xab").unwrap().0);
+        let (matched, stats) = re.matcher().is_match_with_stats("").unwrap();
+        assert!(!matched);
+        assert_eq!(stats.split_count, 1);
+    }
+
+    #[test]
+    fn is_match_as_ref() {
+        let re = Regex::new("a(b|c)+").unwrap();
+        let text = String::from("xacb");
+        assert!(re.is_match("xacb").unwrap());
+        assert!(re.is_match(&text).unwrap());
+        assert!(re.is_match(text.as_str()).unwrap());
+        assert!(re.is_match(text).unwrap());
+        assert!(!re.is_match(String::new()).unwrap());
+    }
+
+    #[test]
+    fn matcher() {
+        let re = Regex::new("(a|b)*c").unwrap();
+        let mut matcher = re.matcher();
+        assert!(matcher.is_match("ababc").unwrap());
+        assert!(!matcher.is_match("abab").unwrap());
+        assert!(matcher.is_match("c").unwrap());
+        assert!(!matcher.is_match("").unwrap());
+
+        // A failed match does not leave threads behind for the next one.
+        let re = Regex::new("a*b").unwrap();
+        let mut matcher = re.matcher();
+        assert!(matches!(
+            matcher.is_match(&("a".repeat(machine::MAX_DEPTH + 1) + "b")),
+            Err(MatchError::DepthLimitExceeded { .. })
+        ));
+        assert!(matcher.is_match("aab").unwrap());
+    }
+
+    #[test]
+    fn matchers_are_independent() {
+        let re = Regex::new("a+").unwrap();
+        let mut m1 = re.matcher();
+        let mut m2 = re.matcher();
+        let long = "b".repeat(100) + "aaa";
+        let first = m1.find(&long).unwrap().unwrap();
+        // Matching with the other matcher in between does not disturb the buffers of the first one.
+        assert_eq!(m2.find("xa").unwrap().unwrap().range(), 1..2);
+        assert_eq!(m1.longest_match("baab").unwrap().unwrap().range(), 1..3);
+        assert_eq!(first.range(), 100..103);
+        assert!(!m2.is_full_match("ab").unwrap());
+        assert!(m1.is_full_match("aa").unwrap());
+        assert!(m2.is_suffix_match("ba").unwrap());
+        assert_eq!(m1.match_prefix_len(&['a', 'a', 'b']).unwrap(), Some(2));
+        assert_eq!(m2.leftmost_prefix(&['b']).unwrap(), None);
+    }
+
+    #[test]
+    fn regex_is_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn matchers_across_threads() {
+        let re = Regex::new("(a|b)+c").unwrap();
+        std::thread::scope(|s| {
+            for text in ["abc", "bac", "ab"] {
+                let re = &re;
+                s.spawn(move || {
+                    let mut matcher = re.matcher();
+                    assert_eq!(matcher.is_match(text).unwrap(), text.ends_with('c'));
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn quantified_empty_group() {
+        let re = Regex::new("a()?b").unwrap();
+        assert!(re.is_full_match("ab").unwrap());
+        assert!(!re.is_match("b").unwrap());
+        assert_eq!(re.instructions(), Regex::new("ab").unwrap().instructions());
+
+        assert!(Regex::new("x()y").unwrap().is_full_match("xy").unwrap());
+        assert!(Regex::new("()*").unwrap().is_full_match("").unwrap());
+    }
+
+    #[test]
+    fn astral_chars() {
+        // A char outside the BMP is a single char to `.`.
+        let re = Regex::new("a.b").unwrap();
+        assert!(re.is_full_match("a😀b").unwrap());
+        let m = Regex::new(".").unwrap().find("😀x").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.range()), (0, 1, 0..4));
+
+        let re = Regex::new("[😀-😏]+").unwrap();
+        let m = re.find("x😐😀😎😏😐").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (2, 5, "😀😎😏"));
+        assert!(!re.is_match("😐").unwrap());
+        assert!(re.is_suffix_match("a😃").unwrap());
+
+        assert!(matches!(
+            Regex::new("[😏-😀]"),
+            Err(SyntaxError::ParseError(ParseError::InvalidClassRange(
+                '😏', '😀'
+            )))
+        ));
+    }
+
+    #[test]
+    fn compile_stages() {
+        let report = Regex::compile_stages("(a|a)+[0-9]").unwrap();
+        let ast = Ast::Concat(vec![Ast::Plus(Ast::Char('a').into()), Ast::Range('0', '9')]);
+        assert_eq!(report.ast, ast);
+        assert_eq!(
+            report.regex.instructions(),
+            Regex::new("a+[0-9]").unwrap().instructions()
+        );
+
+        assert!(matches!(
+            Regex::compile_stages("(a"),
+            Err(SyntaxError::ParseError(ParseError::UnclosedParenthesis))
+        ));
+    }
+
+    #[test]
+    fn is_anchored_start() {
+        let anchored = |pattern| Regex::new(pattern).unwrap().is_anchored_start();
+        assert!(anchored("\\Gab"));
+        assert!(anchored("(\\Ga|\\Gb|\\G)c"));
+        assert!(anchored("(\\Ga)+"));
+        assert!(!anchored("ab"));
+        assert!(!anchored("a\\G"));
+        assert!(!anchored("\\Ga|b"));
+        assert!(!anchored("(\\Ga)?b"));
+        assert!(!anchored(""));
+    }
+
+    #[test]
+    fn new_hex() {
+        let png = Regex::new_hex("89 50 4E 47 0D 0A 1A 0A").unwrap();
+        let header = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+        assert!(png.is_match_bytes(&header).unwrap());
+        assert!(png
+            .is_match_bytes(&[&header[..], &[0x00, 0x00]].concat())
+            .unwrap());
+        assert!(!png.is_match_bytes(&header[..7]).unwrap());
+        assert!(!png.is_match_bytes(&[0x00]).unwrap());
+        assert!(!png.is_match_bytes(b"GIF89a").unwrap());
+        // The signature is found anywhere, unless the pattern is anchored.
+        let data = [&[0x00][..], &header].concat();
+        assert!(png.is_match_bytes(&data).unwrap());
+        let signature = Regex::new_hex("\\G 89 50 4E 47 0D 0A 1A 0A").unwrap();
+        assert!(signature.is_match_bytes(&header).unwrap());
+        assert!(!signature.is_match_bytes(&data).unwrap());
+
+        // `.` matches every byte, newline included, and bytes that are metacharacters are literal.
+        let re = Regex::new_hex("2a .+ 2e [00-1f]").unwrap();
+        assert!(re.is_match_bytes(b"*\n\xff.\x1f").unwrap());
+        assert!(!re.is_match_bytes(b"**.x").unwrap());
+        let re = Regex::new_hex("41 7b 32 7d").unwrap();
+        assert!(re.is_match_bytes(b"A{2}").unwrap());
+        assert!(!re.is_match_bytes(b"AA").unwrap());
+
+        // A regex compiled from chars has no byte semantics, e.g. `é` is not its UTF-8 encoding.
+        let chars = Regex::new("é").unwrap();
+        assert_eq!(
+            chars.is_match_bytes("é".as_bytes()),
+            Err(MatchError::NotHexPattern)
+        );
+        assert_eq!(
+            chars.is_match_bytes(&[0xe9]),
+            Err(MatchError::NotHexPattern)
+        );
+        assert!(Regex::new_hex("c3 a9")
+            .unwrap()
+            .is_match_bytes("é".as_bytes())
+            .unwrap());
+
+        assert!(matches!(
+            Regex::new_hex("89 5"),
+            Err(SyntaxError::ParseError(ParseError::InvalidHexByte))
+        ));
+        // `=` is only an operator in a lookaround, not a byte of the text.
+        assert!(matches!(
+            Regex::new_hex("41=42"),
+            Err(SyntaxError::ParseError(ParseError::InvalidHexByte))
+        ));
+    }
+
+    #[test]
+    fn canonical_key() {
+        let key = |pattern| Regex::new(pattern).unwrap().canonical_key();
+        for pattern in ["(ab)", "((a)b)", "a()b", "a(?#b)b", "(a)(b)", "a{1}b"] {
+            assert_eq!(key(pattern), key("ab"), "{pattern}");
+        }
+        assert_eq!(key("a|a|b"), key("a|b"));
+        assert_eq!(key("a??"), key("a?"));
+        assert_eq!(key("()*a"), key("a"));
+        assert_ne!(key("ab"), key("ba"));
+        assert_ne!(key("a?"), key("a*"));
+        assert_ne!(key("(ab)+"), key("ab+"));
+
+        // A long alternation is a deep AST, which the key is built from without recursing.
+        let words = (0..10_000).map(|i| format!("w{i}")).collect::<Vec<_>>();
+        let re = Regex::new(&words.join("|")).unwrap();
+        assert!(re.canonical_key().starts_with("|&2,'w'0|&2,'w'1|"));
+    }
+
+    #[test]
+    fn is_full_match() {
+        let re = Regex::new("ab+").unwrap();
+        assert!(re.is_full_match("abbb").unwrap());
+        assert!(!re.is_full_match("abbbc").unwrap());
+        assert!(!re.is_full_match("xabbb").unwrap());
+        assert!(re.find("abbbc").unwrap().is_some());
+        assert!(re.find("xabbb").unwrap().is_some());
+
+        // A shorter match found first does not prevent the full one.
+        let re = Regex::new("a|ab").unwrap();
+        assert_eq!(re.find("ab").unwrap().unwrap().as_str(), "a");
+        assert!(re.is_full_match("ab").unwrap());
+
+        let re = Regex::new("x*").unwrap();
+        assert!(re.is_full_match("").unwrap());
+        assert!(!re.is_full_match("y").unwrap());
+    }
+
+    #[test]
+    fn full_line_match() {
+        let re = Regex::new("ab+").unwrap();
+        assert!(re.full_line_match("abb").unwrap());
+        assert!(re.full_line_match("abb\n").unwrap());
+        assert!(re.full_line_match("abb\r\n").unwrap());
+        assert!(!re.full_line_match("abb\r").unwrap());
+        assert!(!re.full_line_match("abb\n\n").unwrap());
+        assert!(!re.full_line_match("xabb\n").unwrap());
+
+        // Only one terminator is removed, so an empty line is still matched as such.
+        let re = Regex::new("x*").unwrap();
+        assert!(re.full_line_match("\n").unwrap());
+        assert!(!re.full_line_match("\r\n\n").unwrap());
+    }
+
+    #[test]
+    fn find_greedy() {
+        let re = Regex::new("a+").unwrap();
+        assert_eq!(re.find("aaa").unwrap().unwrap().end(), 3);
+        let re = Regex::new("a*").unwrap();
+        assert_eq!(re.find("aaab").unwrap().unwrap().end(), 3);
+        let re = Regex::new("a?").unwrap();
+        assert_eq!(re.find("aaa").unwrap().unwrap().end(), 1);
+    }
+
+    #[test]
+    fn find_iter() {
+        let spans = |pattern: &str, text: &str| {
+            let re = Regex::new(pattern).unwrap();
+            re.find_iter(text)
+                .map(|m| m.map(|m| (m.start(), m.end())))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        assert_eq!(spans("a*", "aba"), [(0, 1), (2, 3)]);
+        assert_eq!(spans("a*", "baaab"), [(0, 0), (1, 4), (5, 5)]);
+        assert_eq!(spans("x*", "ab"), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(spans("a*", ""), [(0, 0)]);
+        assert_eq!(spans("ab", "abxabab"), [(0, 2), (3, 5), (5, 7)]);
+        assert_eq!(spans("ab", "xyz"), []);
+
+        let re = Regex::new("é.").unwrap();
+        let matches = re
+            .find_iter("aébéc")
+            .map(|m| m.map(|m| m.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(matches, ["éb", "éc"]);
+
+        // The spans are mapped back to the text across many matches.
+        let text = "ö1 xy_2, ".repeat(1000);
+        let re = Regex::new("[a-z0-9_]+").unwrap();
+        let mut count = 0;
+        for (i, m) in re.find_iter(&text).enumerate() {
+            let m = m.unwrap();
+            assert_eq!(m.as_str(), if i % 2 == 0 { "1" } else { "xy_2" });
+            assert_eq!(&text[m.range()], m.as_str());
+            count += 1;
+        }
+        assert_eq!(count, 2000);
+    }
+
+    #[test]
+    fn split() {
+        let split = |pattern, text| Regex::new(pattern).unwrap().split(text).unwrap();
+        assert_eq!(split("b+", "abbcbd"), ["a", "c", "d"]);
+        assert_eq!(split("b", "bab"), ["", "a", ""]);
+        assert_eq!(split("b", "acd"), ["acd"]);
+        assert_eq!(split("b", ""), [""]);
+        assert_eq!(split("é", "aébéc"), ["a", "b", "c"]);
+        // Every empty match splits, but not right after another match.
+        assert_eq!(split("a*", "bac"), ["", "b", "c", ""]);
+
+        // The parts point into the text.
+        let text = String::from("x--yy-z");
+        let parts = Regex::new("-+").unwrap().split(&text).unwrap();
+        assert_eq!(parts, ["x", "yy", "z"]);
+        for (part, offset) in parts.iter().zip([0, 3, 6]) {
+            assert_eq!(part.as_ptr(), text[offset..].as_ptr());
+        }
+    }
+
+    #[test]
+    fn replace_all_into() {
+        let mut out = String::from("stale");
+        let re = Regex::new("ab+").unwrap();
+        re.replace_all_into("xabyabbbz", "-", &mut out).unwrap();
+        assert_eq!(out, "x-y-z");
+        re.replace_all_into("ab", "<ab>", &mut out).unwrap();
+        assert_eq!(out, "<ab>");
+        re.replace_all_into("no match", "-", &mut out).unwrap();
+        assert_eq!(out, "no match");
+        re.replace_all_into("", "-", &mut out).unwrap();
+        assert_eq!(out, "");
+
+        let re = Regex::new("é+").unwrap();
+        re.replace_all_into("aéébé", "e", &mut out).unwrap();
+        assert_eq!(out, "aebe");
+
+        // Empty matches are replaced, but not right after a match.
+        let re = Regex::new("a*").unwrap();
+        re.replace_all_into("baaab", "X", &mut out).unwrap();
+        assert_eq!(out, "XbXbX");
+    }
+
+    #[test]
+    fn replace_all() {
+        let re = Regex::new("a*").unwrap();
+        // The empty match at 1 right after `a` and the one at the end right after `a` are not replaced.
+        assert_eq!(re.replace_all("aba", "X").unwrap(), "XbX");
+        assert_eq!(re.replace_all("", "X").unwrap(), "X");
+        assert_eq!(re.replace_all("b", "X").unwrap(), "XbX");
+        assert_eq!(re.replace_all("bb", "X").unwrap(), "XbXbX");
+        assert_eq!(re.replace_all("aabaa", "X").unwrap(), "XbX");
+        assert_eq!(re.replace_all("ébaé", "<>").unwrap(), "<>é<>b<>é<>");
+
+        // Adjacent non-empty matches are each replaced once.
+        let re = Regex::new("ab").unwrap();
+        assert_eq!(re.replace_all("ababxab", "-").unwrap(), "--x-");
+
+        let re = Regex::new("").unwrap();
+        assert_eq!(re.replace_all("abc", "-").unwrap(), "-a-b-c-");
+    }
+
+    #[test]
+    fn replace_all_template() {
+        let re = Regex::new("b+").unwrap();
+        let replace = |rep| re.replace_all("abbc", rep).unwrap();
+        assert_eq!(replace("[$0]"), "a[bb]c");
+        assert_eq!(replace("[${0}]"), "a[bb]c");
+        assert_eq!(replace("$00"), "abbc");
+        assert_eq!(replace("$$0"), "a$0c");
+        // A `$` at the end or in an unterminated `${` is literal.
+        assert_eq!(replace("$"), "a$c");
+        assert_eq!(replace("x$"), "ax$c");
+        assert_eq!(replace("${1"), "a${1c");
+        assert_eq!(replace("${0"), "a${0c");
+        assert_eq!(replace("$-"), "a$-c");
+        // `$1a` is the group `1a`, and there is no group but 0.
+        assert_eq!(replace("$1a"), "ac");
+        assert_eq!(replace("${1}a"), "aac");
+        assert_eq!(replace("$0a"), "ac");
+        assert_eq!(replace("${0}a"), "abbac");
+        assert_eq!(replace("${}"), "a${}c");
+        assert_eq!(replace("é$0é"), "aébbéc");
+    }
+
+    #[test]
+    fn replace_with() {
+        let re = Regex::new("b+").unwrap();
+        let double = |m: &str| m.repeat(2);
+        assert_eq!(re.replace_with("abbcb", double).unwrap(), "abbbbcb");
+        assert_eq!(re.replace_with("ac", double).unwrap(), "ac");
+        assert_eq!(re.replace_with("", double).unwrap(), "");
+
+        // Byte offsets are used for slicing.
+        let re = Regex::new("ö").unwrap();
+        assert_eq!(re.replace_with("aöb", double).unwrap(), "aööb");
+
+        // An empty match is replaced too.
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(
+            re.replace_with("ab", |m| format!("[{}]", m)).unwrap(),
+            "[]ab"
+        );
+    }
+
+    #[test]
+    fn min_max_len() {
+        let len = |pattern| {
+            let re = Regex::new(pattern).unwrap();
+            (re.min_len(), re.max_len())
+        };
+        assert_eq!(len("abc"), (3, Some(3)));
+        assert_eq!(len("a?b"), (1, Some(2)));
+        assert_eq!(len("a*"), (0, None));
+        assert_eq!(len("x(ab|c)+"), (2, None));
+    }
+
+    #[test]
+    fn min_len() {
+        let re = Regex::new("abcdef").unwrap();
+        assert_eq!(re.min_len, 6);
+        assert!(!re.is_match("abc").unwrap());
+        assert!(re.is_match("abcdef").unwrap());
+        assert!(re.is_match("abcdefg").unwrap());
+
+        // Multi-byte chars are not rejected early by the byte length.
+        let re = Regex::new("ööö").unwrap();
+        assert!(!re.is_match("öö").unwrap());
+        assert!(re.is_match("ööö").unwrap());
+
+        let re = Regex::new("a*").unwrap();
+        assert_eq!(re.min_len, 0);
+        assert!(re.is_match("").unwrap());
+    }
+
+    #[test]
+    fn search_start() {
+        let re = Regex::new("\\Gabc").unwrap();
+        let m = re.find_at("xxabcabc", 2).unwrap().unwrap();
+        assert_eq!((m.start(), m.end()), (2, 5));
+        assert!(re.find_at("xxabcabc", 1).unwrap().is_none());
+        assert!(re.find("xxabc").unwrap().is_none());
+        assert!(re.is_match("abc").unwrap());
+        assert!(re.is_suffix_match("abc").unwrap());
+        assert!(!re.is_suffix_match("xabc").unwrap());
+
+        // Every match of find_iter must start where the previous one ended.
+        let re = Regex::new("\\G(ab|c)").unwrap();
+        let spans = |text| {
+            re.find_iter(text)
+                .map(|m| m.map(|m| (m.start(), m.end())))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(spans("abcab"), [(0, 2), (2, 3), (3, 5)]);
+        assert_eq!(spans("abxcab"), [(0, 2)]);
+
+        assert!(Regex::new("a\\Gb").unwrap().find("ab").unwrap().is_none());
+    }
+
+    #[test]
+    fn char_eq() {
+        let re = RegexBuilder::new("F0+")
+            .char_eq(|c, cc| c == cc || matches!((c, cc), ('O', '0') | ('0', 'O')))
+            .build()
+            .unwrap();
+        assert!(re.is_full_match("F00").unwrap());
+        assert!(re.is_full_match("FOO").unwrap());
+        assert!(re.is_full_match("F0O").unwrap());
+        assert!(!re.is_full_match("Foo").unwrap());
+        assert_eq!(re.find("xFO0!").unwrap().unwrap().as_str(), "FO0");
+        assert!(re.is_suffix_match("xFOO").unwrap());
+
+        // The default is plain equality.
+        assert!(!Regex::new("F0+").unwrap().is_match("FOO").unwrap());
+    }
+
+    #[test]
+    fn optimize() {
+        let re = RegexBuilder::new("x(abc|abd|ab|abcd)|y(foobar|bar)")
+            .optimize(true)
+            .reverse_program(true)
+            .build()
+            .unwrap();
+        let naive = Regex::new("x(abc|abd|ab|abcd)|y(foobar|bar)").unwrap();
+        for text in [
+            "xabc", "xabd", "xab", "xabcd", "xabe", "yfoobar", "ybar", "ybaz", "zxabd",
+        ] {
+            assert_eq!(re.find(text).unwrap(), naive.find(text).unwrap());
+            assert_eq!(
+                re.is_suffix_match(text).unwrap(),
+                naive.is_suffix_match(text).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn unreachable_instructions() {
+        // Code generation leaves no dead instruction behind.
+        for pattern in [
+            "x(abc|abd|ab|abcd)|y(foobar|bar)",
+            "(a|b)*c?d+",
+            "(a*|b)+|()",
+            "a(?=b|c)(?<!x)[a-z_]",
+            "\\G(ab){2,3}.",
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            assert_eq!(re.unreachable_instructions(), [], "{pattern}");
+        }
+
+        // Nor does removing the jumps, which drops those that every instruction goes past, e.g. the jump closing
+        // the loop of `a*` once its split goes past it.
+        for pattern in [
+            "(a*|b)+|()",
+            "x(abc|abd|ab|abcd)|y(foobar|bar)",
+            "((a|b)*|c)+d",
+            "(a|(b|c)*)?(?=d|e)",
+            "(ab|a)*|(?<!x|y)z",
+        ] {
+            let re = RegexBuilder::new(pattern).optimize(true).build().unwrap();
+            assert_eq!(re.unreachable_instructions(), [], "{pattern}");
+        }
+    }
+
+    #[test]
+    fn match_prefix_len() {
+        let len = |pattern, text: &str| {
+            let re = Regex::new(pattern).unwrap();
+            re.match_prefix_len(&text.chars().collect::<Vec<_>>())
+                .unwrap()
+        };
+        assert_eq!(len("a+", "aaab"), Some(3));
+        assert_eq!(len("a+", "baaa"), None);
+        assert_eq!(len("a*", "b"), Some(0));
+        // The longest alternative is taken, not the first one.
+        assert_eq!(len("a|ab", "abc"), Some(2));
+        assert_eq!(len("ö+", "ööx"), Some(2));
+    }
+
+    #[test]
+    fn empty() {
+        let re = Regex::new("").unwrap();
+        assert!(re.is_match("").unwrap());
+        assert!(re.is_match("abc").unwrap());
+        assert!(re.is_full_match("").unwrap());
+        assert!(!re.is_full_match("a").unwrap());
+        assert_eq!((re.min_len(), re.max_len()), (0, Some(0)));
+
+        let spans = re
+            .find_iter("ab")
+            .map(|m| m.map(|m| (m.start(), m.end())))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(spans, [(0, 0), (1, 1), (2, 2)]);
+
+        let mut out = String::new();
+        re.replace_all_into("ab", "-", &mut out).unwrap();
+        assert_eq!(out, "-a-b-");
+    }
+
+    #[test]
+    fn leftmost_prefix() {
+        let prefix = |pattern, text: &str| {
+            let re = Regex::new(pattern).unwrap();
+            re.leftmost_prefix(&text.chars().collect::<Vec<_>>())
+                .unwrap()
+        };
+        assert_eq!(prefix("(0|1|2|3|4|5|6|7|8|9)+", "123abc"), Some(3));
+        assert_eq!(prefix("(0|1|2|3|4|5|6|7|8|9)+", "abc123"), None);
+        // The first alternative wins, even if a later one is longer.
+        assert_eq!(prefix("a|ab", "abc"), Some(1));
+        assert_eq!(prefix("x*", "abc"), Some(0));
+    }
+
+    #[test]
+    fn any_match_in() {
+        for pattern in [
+            "ERROR.*timeout",
+            "(foo|xfoo)bar",
+            "a+b",
+            "\\Gab",
+            "é?",
+            "(?=x)y",
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            for text in [
+                "",
+                "ERROR timeout",
+                "ERRORtimeout!",
+                "INFO timeout",
+                "xfoobar",
+                "fobar",
+                "aab",
+                "ab",
+                "é",
+            ] {
+                assert_eq!(
+                    re.any_match_in(text).unwrap(),
+                    re.is_match(text).unwrap(),
+                    "{pattern} {text}"
+                );
+            }
+        }
+
+        // The length is checked before the text is rejected.
+        let re = RegexBuilder::new("x").max_input_len(2).build().unwrap();
+        assert_eq!(
+            re.any_match_in("abc"),
+            Err(MatchError::InputTooLong { len: 3, max: 2 })
+        );
+        // A text that passes the filters is still matched.
+        let re = Regex::new("a*c").unwrap();
+        let text = format!("{}c", "a".repeat(machine::MAX_DEPTH + 1));
+        assert!(re.any_match_in(&text).is_err());
+    }
+
+    #[test]
+    fn offset_mode() {
+        let text = "äa ööab éb";
+        let chars = Regex::new("a+b|é").unwrap();
+        let bytes = RegexBuilder::new("a+b|é")
+            .offset_mode(OffsetMode::Byte)
+            .build()
+            .unwrap();
+        assert_eq!(chars.offset_mode(), OffsetMode::Char);
+        assert_eq!(bytes.offset_mode(), OffsetMode::Byte);
+
+        let spans = |re: &Regex| {
+            re.find_iter(text)
+                .map(|m| m.map(|m| (m.start(), m.end(), m.as_str())))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(spans(&chars), [(5, 7, "ab"), (8, 9, "é")]);
+        assert_eq!(spans(&bytes), [(8, 10, "ab"), (11, 13, "é")]);
+        for (re, start) in [(&chars, 3), (&bytes, 4)] {
+            for m in [
+                re.find(text),
+                re.longest_match(text),
+                re.find_at(text, start),
+            ] {
+                assert_eq!(m.unwrap().unwrap().as_str(), "ab");
+            }
+        }
+        // Every byte offset is that of the char offset.
+        let m = chars.find(text).unwrap().unwrap();
+        assert_eq!(text.char_indices().nth(m.start()).unwrap().0, 8);
+        assert_eq!(bytes.find(text).unwrap().unwrap().range(), 8..10);
+
+        // A byte offset to start at must be on a char boundary.
+        assert_eq!(
+            bytes.find_at(text, 1),
+            Err(MatchError::NotCharBoundary { offset: 1 })
+        );
+        assert_eq!(bytes.find_at(text, 11).unwrap().unwrap().start(), 11);
+        assert_eq!(bytes.find_at(text, 14).unwrap(), None);
+        assert_eq!(chars.find_at(text, 11).unwrap(), None);
+
+        let re = RegexBuilder::new("äöx")
+            .offset_mode(OffsetMode::Byte)
+            .build()
+            .unwrap();
+        assert_eq!(re.consumed_prefix_len("äöy").unwrap(), 4);
+        assert_eq!(
+            Regex::new("äöx")
+                .unwrap()
+                .consumed_prefix_len("äöy")
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn consumed_prefix_len() {
+        let consumed = |pattern, text| {
+            Regex::new(pattern)
+                .unwrap()
+                .consumed_prefix_len(text)
+                .unwrap()
+        };
+        assert_eq!(consumed("abcd", "abcX"), 3);
+        assert_eq!(consumed("abcd", "abcdef"), 4);
+        assert_eq!(consumed("abcd", "Xbcd"), 0);
+        assert_eq!(consumed("abcd", ""), 0);
+        // The furthest of all the alternatives, even after a match is found.
+        assert_eq!(consumed("ab|abcd", "abcX"), 3);
+        assert_eq!(consumed("a*b", "aaac"), 3);
+        // A lookahead does not consume the chars it looks at.
+        assert_eq!(consumed("a(?=bc)x", "abc"), 1);
+    }
+
+    #[test]
+    fn simplify() {
+        for pattern in [
+            "a??",
+            "a*?",
+            "a+?",
+            "a++",
+            "(ab)c|abc",
+            "(a|a)+b",
+            "x((y))z",
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            let ast = parser::parse(pattern).unwrap();
+            let naive = Machine::new(codegen::generate_code(ast, true, false).unwrap());
+            for text in ["", "a", "ab", "abc", "aab", "xyz", "xz"] {
+                let chars = text.chars().collect::<Vec<_>>();
+                assert_eq!(
+                    re.is_full_match(text).unwrap(),
+                    naive.is_full_match(&chars).unwrap()
+                );
+                assert_eq!(
+                    re.leftmost_prefix(&chars).unwrap(),
+                    naive
+                        .match_end(&chars, Sp(0), Sp(0))
+                        .unwrap()
+                        .map(|sp| sp.0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn instructions() {
+        let re = Regex::new("a|b").unwrap();
+        assert_eq!(
+            re.instructions(),
+            [
+                /*   :0 */ Instruction::Split(Pc(1), Pc(3)), // L1, L2
+                /* L1:1 */ Instruction::Char('a'),
+                /*   :2 */ Instruction::Jmp(Pc(4)), // L3
+                /* L2:3 */ Instruction::Char('b'),
+                /* L3:4 */ Instruction::Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn ascii_class_instructions() {
+        let re = Regex::new("[a-c]|_").unwrap();
+        let bits = 0b111 << (b'a' - 64) | 1 << (b'_' - 64);
+        assert_eq!(
+            re.instructions(),
+            [Instruction::AsciiClass([0, bits]), Instruction::Match]
+        );
+    }
+
+    #[test]
+    fn empty_alternative() {
+        // An empty last alternative compiles to the same program as `?`.
+        let optional = RegexBuilder::new("a?").optimize(true).build().unwrap();
+        for pattern in ["(a|())", "a|()|()"] {
+            let re = RegexBuilder::new(pattern).optimize(true).build().unwrap();
+            assert_eq!(re.instructions(), optional.instructions());
+        }
+        // An empty first alternative prefers the empty string, unlike `?`.
+        let re = RegexBuilder::new("()|a").optimize(true).build().unwrap();
+        assert_ne!(re.instructions(), optional.instructions());
+        assert_eq!(re.find("a").unwrap().unwrap().as_str(), "");
+        assert_eq!(optional.find("a").unwrap().unwrap().as_str(), "a");
+    }
+
+    #[test]
+    fn repetition() {
+        let re = Regex::new("(ab){2,3}c").unwrap();
+        assert!(re.is_full_match("ababc").unwrap());
+        assert!(re.is_full_match("abababc").unwrap());
+        assert!(!re.is_full_match("abc").unwrap());
+        assert!(!re.is_full_match("ababababc").unwrap());
+
+        let re = Regex::new("x{b}").unwrap();
+        assert!(re.is_full_match("x{b}").unwrap());
+    }
+
+    #[test]
+    fn required_chars() {
+        let re = Regex::new("(a|b)+z").unwrap();
+        assert!(re.is_match("abz").unwrap());
+        assert!(re.is_match("xbzy").unwrap());
+        assert!(!re.is_match(&"ab".repeat(1000)).unwrap());
+        assert!(!re.is_match("z").unwrap());
+
+        // The chars of a lookbehind are outside the match, so only `y` is required.
+        let re = Regex::new("(?<=x)y").unwrap();
+        assert!(re.is_match("xy").unwrap());
+        assert!(!re.is_match("y").unwrap());
+
+        // `char_eq` may match a literal with another char.
+        let re = RegexBuilder::new("ab")
+            .char_eq(|c, cc| c.eq_ignore_ascii_case(&cc))
+            .build()
+            .unwrap();
+        assert!(re.is_match("xAB").unwrap());
+    }
+
+    #[test]
+    fn nesting() {
+        let max = parser::MAX_NESTING;
+        // Patterns just below the limit compile and match without overflowing the stack.
+        for pattern in [
+            format!("a{}", "*".repeat(max - 1)),
+            "(a".repeat(max / 3) + &")*".repeat(max / 3),
+            "(b|".repeat(max / 3) + "a" + &")+".repeat(max / 3),
+            "(?=".repeat(max / 2 - 1) + "a" + &")".repeat(max / 2 - 1),
+            "(?<!".repeat(max / 2 - 1) + "a" + &")".repeat(max / 2 - 1),
+        ] {
+            let re = RegexBuilder::new(&pattern).optimize(true).build().unwrap();
+            assert!(re.is_match("ab").unwrap());
+            re.is_suffix_match("ab").unwrap();
+        }
+
+        assert!(matches!(
+            Regex::new(&format!("a{}", "*".repeat(max))),
+            Err(SyntaxError::ParseError(ParseError::NestingTooDeep))
+        ));
+    }
+
+    #[test]
+    fn required_literals() {
+        let re = Regex::new("foo.*bar").unwrap();
+        assert_eq!(re.required_literals(), ["foo", "bar"]);
+        // The literals are taken from the simplified pattern, so a group does not split a run.
+        let re = Regex::new("(ab)c()d?").unwrap();
+        assert_eq!(re.required_literals(), ["abc"]);
+
+        let re = RegexBuilder::new("foo")
+            .char_eq(|c, cc| c.eq_ignore_ascii_case(&cc))
+            .build()
+            .unwrap();
+        assert!(re.required_literals().is_empty());
+    }
+
+    #[test]
+    fn alternation_overlaps() {
+        assert!(Regex::new("(abc|ab)").unwrap().alternation_overlaps());
+        assert!(!Regex::new("(a|b)").unwrap().alternation_overlaps());
+        // The overlap is found in the simplified pattern, before prefixes are factored out.
+        let re = RegexBuilder::new("x(abc|abd)")
+            .optimize(true)
+            .build()
+            .unwrap();
+        assert!(re.alternation_overlaps());
+        assert!(!Regex::new("a|a").unwrap().alternation_overlaps());
+        assert!(!Regex::new("[a-cb]").unwrap().alternation_overlaps());
+        assert!(!Regex::new("x[a-c0-9b]y").unwrap().alternation_overlaps());
+    }
+
+    #[test]
+    fn ascii_class() {
+        let re = Regex::new("[a-z_0]+").unwrap();
+        assert!(matches!(re.instructions()[0], Instruction::AsciiClass(_)));
+        assert!(re.is_full_match("ab_0z").unwrap());
+        assert!(!re.is_match("1A").unwrap());
+        assert!(!re.is_match("é").unwrap());
+
+        // A class with a non-ASCII char is still tried operand by operand.
+        let re = Regex::new("[a-zé]+").unwrap();
+        assert!(matches!(re.instructions()[0], Instruction::Split(..)));
+        assert!(re.is_full_match("aéz").unwrap());
+
+        // `char_eq` is used for the chars and ranges of a class too.
+        let re = RegexBuilder::new("[a-c]|x")
+            .char_eq(|c, cc| c.eq_ignore_ascii_case(&cc))
+            .build()
+            .unwrap();
+        assert!(re.is_full_match("X").unwrap());
+        assert!(re.is_full_match("b").unwrap());
+        assert!(re.is_full_match("B").unwrap());
+    }
+
+    #[test]
+    fn case_insensitive_classes() {
+        let mut builder = RegexBuilder::new("[a-z]+|q[xy]");
+        builder.char_eq(|c, cc| c.eq_ignore_ascii_case(&cc));
+        let re = builder.build().unwrap();
+        assert!(re.is_full_match("A").unwrap());
+        assert!(re.is_full_match("aBc").unwrap());
+        assert!(re.is_full_match("QY").unwrap());
+        assert!(!re.is_full_match("1").unwrap());
+        assert_eq!(re.find_at("ABc", 1).unwrap().unwrap().as_str(), "Bc");
+
+        // The classes are exempt, but not the literals.
+        let re = builder.case_insensitive_classes(false).build().unwrap();
+        assert!(!re.is_full_match("A").unwrap());
+        assert!(re.is_full_match("abc").unwrap());
+        assert!(!re.is_full_match("aBc").unwrap());
+        assert!(re.is_full_match("Qy").unwrap());
+        assert!(!re.is_full_match("QY").unwrap());
+        assert_eq!(re.find("ABc").unwrap().unwrap().as_str(), "c");
+    }
+
+    #[test]
+    fn disable_operators() {
+        let re = RegexBuilder::new("a|b")
+            .disable_operators(&['|'])
+            .build()
+            .unwrap();
+        assert!(re.is_full_match("a|b").unwrap());
+        assert!(!re.is_full_match("a").unwrap());
+        assert!(!re.is_full_match("b").unwrap());
+
+        // `(` alone would be an unclosed parenthesis.
+        let re = RegexBuilder::new("f(x+")
+            .disable_operators(&['(', ')'])
+            .build()
+            .unwrap();
+        assert!(re.is_full_match("f(xx").unwrap());
+
+        // A literal `?` does not open a lookahead after `(`.
+        let re = RegexBuilder::new("(?=a)a")
+            .disable_operators(&['?'])
+            .build()
+            .unwrap();
+        assert!(!re.is_full_match("a").unwrap());
+        assert!(re.is_full_match("?=aa").unwrap());
+    }
+
+    #[test]
+    fn from_str() {
+        let re = "ab*".parse::<Regex>().unwrap();
+        assert!(re.is_full_match("abbb").unwrap());
+        assert!(matches!(
+            "a|".parse::<Regex>(),
+            Err(SyntaxError::ParseError(ParseError::MissingOperand))
+        ));
+
+        let re = Regex::try_from("ab*").unwrap();
+        assert!(re.is_full_match("a").unwrap());
+        assert!(matches!(
+            Regex::try_from("(a"),
+            Err(SyntaxError::ParseError(ParseError::UnclosedParenthesis))
+        ));
+    }
+
+    #[test]
+    fn range() {
+        let text = "xöyöö";
+        let re = Regex::new("ö+").unwrap();
+        let ranges = re
+            .find_iter(text)
+            .map(|m| m.map(|m| m.range()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ranges, [1..3, 4..8]);
+        assert_eq!(&text[ranges[1].clone()], "öö");
+
+        let m = Regex::new("x*").unwrap().find("yy").unwrap().unwrap();
+        assert_eq!(m.range(), 0..0);
+    }
+
+    #[test]
+    fn look_ahead() {
+        // Contains a digit.
+        let re = Regex::new("(?=.*(0|1|2|3|4|5|6|7|8|9)).+").unwrap();
+        assert!(re.is_full_match("a1b").unwrap());
+        assert!(!re.is_full_match("abc").unwrap());
+
+        let re = Regex::new("a(?!b)").unwrap();
+        let m = re.find("abac").unwrap().unwrap();
+        assert_eq!((m.start(), m.end()), (2, 3));
+        assert!(re.is_suffix_match("xa").unwrap());
+        assert!(!re.is_suffix_match("ab").unwrap());
+
+        let re = Regex::new("(?=ab)a").unwrap();
+        assert!(!re.is_suffix_match("xxa").unwrap());
+        assert_eq!(re.find("aab").unwrap().unwrap().start(), 1);
+    }
+
+    #[test]
+    fn dot_matches_newline() {
+        let re = Regex::new("a.b").unwrap();
+        assert!(re.dot_matches_newline());
+        assert!(re.instructions().contains(&Instruction::AnyByte));
+        assert!(re.is_match("a\nb").unwrap());
+
+        let re = RegexBuilder::new("a.b")
+            .dot_matches_newline(false)
+            .build()
+            .unwrap();
+        assert!(!re.dot_matches_newline());
+        assert!(re
+            .instructions()
+            .contains(&Instruction::AnyByteExceptNewline));
+        assert!(!re.is_match("a\nb").unwrap());
+        assert!(re.is_suffix_match("a-b").unwrap());
+
+        // The inline flag overrides the option within its group.
+        let re = RegexBuilder::new("a(?s:.)b.")
+            .dot_matches_newline(false)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a\nb-").unwrap());
+        assert!(!re.is_match("a\nb\n").unwrap());
+        let re = Regex::new("(?-s)a.b|(?s:x.)y.").unwrap();
+        assert!(!re.is_match("a\nb").unwrap());
+        assert!(re.is_match("x\ny-").unwrap());
+        assert!(!re.is_match("x\ny\n").unwrap());
+        let re = Regex::new("(a(?-s).)*.").unwrap();
+        assert_eq!(re.find("a\n").unwrap().unwrap().range(), 0..1);
+        assert_eq!(re.find("aa\n").unwrap().unwrap().range(), 0..3);
+    }
+
+    #[test]
+    fn max_input_len() {
+        let re = RegexBuilder::new("b").max_input_len(3).build().unwrap();
+        let too_long = Err(MatchError::InputTooLong { len: 4, max: 3 });
+        assert!(re.is_match("abc").unwrap());
+        assert_eq!(re.is_match("aabc"), too_long);
+        assert_eq!(re.is_full_match("bbbb"), too_long);
+        assert_eq!(re.find("aabc").map(|m| m.is_some()), too_long);
+        assert_eq!(re.is_suffix_match("aaab"), too_long);
+        assert_eq!(re.is_match_bytes(b"abcd"), Err(MatchError::NotHexPattern));
+        // The length is counted in bytes for a `&str`, and in chars for a `&[char]`.
+        assert!(re.is_match("éb").unwrap());
+        assert_eq!(re.is_match("éé"), too_long);
+        assert_eq!(re.leftmost_prefix(&['b', 'é', 'é']).unwrap(), Some(1));
+        assert_eq!(
+            re.match_prefix_len(&['b'; 4]).map(|len| len.is_some()),
+            too_long
+        );
+
+        // `find_iter` yields the error and stops.
+        let mut matches = re.find_iter("bbbb");
+        assert_eq!(
+            matches.next().unwrap(),
+            Err(MatchError::InputTooLong { len: 4, max: 3 })
+        );
+        assert!(matches.next().is_none());
+        assert_eq!(
+            re.split("bbbb"),
+            Err(MatchError::InputTooLong { len: 4, max: 3 })
+        );
+        assert_eq!(re.split("abc").unwrap(), ["a", "c"]);
+    }
+
+    #[test]
+    fn look_behind() {
+        let re = Regex::new("(?<=$)(0|1|2|3|4|5|6|7|8|9)+").unwrap();
+        let m = re.find("$100").unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (1, 4, "100"));
+        assert!(re.find("100").unwrap().is_none());
+        assert!(re.is_suffix_match("cost: $100").unwrap());
+        assert!(!re.is_suffix_match("cost: 100").unwrap());
+
+        let re = Regex::new("(?<!a)b").unwrap();
+        assert_eq!(re.find("abcb").unwrap().unwrap().start(), 3);
+        assert!(re.is_suffix_match("b").unwrap());
+        assert!(!re.is_suffix_match("ab").unwrap());
+
+        // The span of a match between lookarounds covers neither of them.
+        let re = Regex::new("(?<=a)b+(?!c)").unwrap();
+        assert_eq!(re.find("bbabbcabbb").unwrap().unwrap().range(), 3..4);
+        assert_eq!(re.find_at("bbabbcabbb", 5).unwrap().unwrap().range(), 7..10);
+        assert_eq!(re.longest_match("abbc").unwrap().unwrap().range(), 1..2);
+
+        assert!(matches!(
+            Regex::new("(?<=a*)b"),
+            Err(SyntaxError::ParseError(ParseError::VariableWidthLookbehind))
+        ));
+    }
+
+    #[test]
+    fn long_alternation() {
+        let pattern = (0..5000)
+            .map(|i| alloc::format!("w{i}x"))
+            .collect::<Vec<_>>()
+            .join("|");
+        let re = Regex::new(&pattern).unwrap();
+        assert!(re.is_match("w0x").unwrap());
+        assert!(re.is_match("w42x").unwrap());
+        assert!(!re.is_match("w42").unwrap());
+    }
+
+    #[test]
+    fn nullable_loops() {
+        // A loop whose operand matches the empty string ends once the operand matches nothing more.
+        assert!(compile_and_match("(a*)*b", "aab").unwrap());
+        assert!(compile_and_match("(a?)*", "aaa").unwrap());
+        assert!(!compile_and_match("(a|b?)+c", "abx").unwrap());
+        // The machine stops an iteration that consumes nothing, also when the operand has an assertion.
+        assert!(compile_and_match("(?=a)+", "a").unwrap());
+        assert!(compile_and_match("(\\G)*x", "x").unwrap());
+        assert!(compile_and_match("(a?b?)*c", "abbac").unwrap());
+        assert!(!compile_and_match("(?=a)+b", "a").unwrap());
+        // The loop check adds a few instructions to every loop, rather than copying its operand.
+        for (pattern, max) in [("((a?){1000})*", 2_010), ("((a?b?){300})*", 1_210)] {
+            let len = Regex::new(pattern).unwrap().machine.instructions().len();
+            assert!(len <= max, "{pattern}: {len}");
+        }
+    }
+
+    #[test]
+    fn is_suffix_match() {
+        let re = Regex::new("ab(cd|e)+").unwrap();
+        assert!(re.reverse_machine.is_none());
+        let reverse = RegexBuilder::new("ab(cd|e)+")
+            .reverse_program(true)
+            .build()
+            .unwrap();
+        assert!(reverse.reverse_machine.is_some());
+        for re in [re, reverse] {
+            assert!(re.is_suffix_match("abcd").unwrap());
+            assert!(re.is_suffix_match("xxabecd").unwrap());
+            assert!(!re.is_suffix_match("abcdx").unwrap());
+            assert!(!re.is_suffix_match("ab").unwrap());
+            assert!(!re.is_suffix_match("").unwrap());
+        }
+
+        // A lookahead cannot be reversed.
+        let re = RegexBuilder::new("a(?=b)")
+            .reverse_program(true)
+            .build()
+            .unwrap();
+        assert!(re.reverse_machine.is_none());
+        assert!(!re.is_suffix_match("ab").unwrap());
     }
 
-    /// Check if the text matches the regular expression.
-    pub fn is_match(&self, text: &str) -> Result<bool, MatchError> {
-        let chars = text.chars().collect::<Vec<_>>();
-        self.machine.is_match(&chars)
+    #[test]
+    fn error() {
+        assert!(compile_and_match("ab*", "abbb").unwrap());
+        assert!(matches!(
+            compile_and_match("a|", "a"),
+            Err(Error::SyntaxError(SyntaxError::ParseError(
+                ParseError::MissingOperand
+            )))
+        ));
+        assert!(matches!(
+            Error::from(MatchError::InstructionNotFound),
+            Error::MatchError(MatchError::InstructionNotFound)
+        ));
+        assert!(matches!(
+            Error::from(ValidationError::MissingMatch),
+            Error::SyntaxError(SyntaxError::ValidationError(ValidationError::MissingMatch))
+        ));
+        assert!(matches!(
+            Error::from(GenerateCodeError::UnsupportedConstruct { construct: "x" }),
+            Error::SyntaxError(SyntaxError::GenerateCodeError(
+                GenerateCodeError::UnsupportedConstruct { .. }
+            ))
+        ));
+        assert!(matches!(
+            compile_and_match("a*b", &format!("{}b", "a".repeat(machine::MAX_DEPTH + 1))),
+            Err(Error::MatchError(MatchError::DepthLimitExceeded { .. }))
+        ));
     }
 }
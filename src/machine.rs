@@ -1,11 +1,12 @@
-use thiserror::Error;
+use alloc::vec::Vec;
+use core::fmt;
 
-use crate::codegen::{Instruction, Pc};
+use crate::codegen::{self, Instruction, Pc};
 
 /// String pointer.
 /// This is used to point to the current character in the text.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct Sp(usize);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Sp(pub usize);
 
 impl Sp {
     fn inc<E>(&mut self, err: impl Fn() -> E) -> Result<Self, E> {
@@ -18,76 +19,536 @@ impl Sp {
     }
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
+/// Maximum number of threads waiting to be backtracked to.
+///
+/// Every split passed without backtracking leaves a thread behind, e.g. `a*` leaves one per `a` consumed, so this also
+/// bounds how long a text such a loop can consume.
+pub const MAX_DEPTH: usize = 1 << 20;
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum MatchError {
-    #[error("program counter overflow occured")]
     PcOverflow,
-    #[error("stack pointer overflow occured")]
     SpOverflow,
-    #[error("instruction not found")]
     InstructionNotFound,
+    /// More than `MAX_DEPTH` (2^20) threads were left to backtrack to, at the split `pc` with the string pointer `sp`.
+    DepthLimitExceeded {
+        pc: Pc,
+        sp: Sp,
+    },
+    /// The text is `len` long, more than the `max` set with `RegexBuilder::max_input_len`.
+    InputTooLong {
+        len: usize,
+        max: usize,
+    },
+    /// The byte offset given to start matching at is not on a char boundary of the text, e.g. with
+    /// [`crate::OffsetMode::Byte`].
+    NotCharBoundary {
+        offset: usize,
+    },
+    /// Bytes were matched with a regex that was not compiled from hex with [`crate::Regex::new_hex`], whose chars
+    /// have no byte semantics.
+    NotHexPattern,
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PcOverflow => write!(f, "program counter overflow occured"),
+            Self::SpOverflow => write!(f, "stack pointer overflow occured"),
+            Self::InstructionNotFound => write!(f, "instruction not found"),
+            Self::DepthLimitExceeded { pc, sp } => write!(
+                f,
+                "backtracking depth limit exceeded at PC {} and SP {}",
+                pc.0, sp.0
+            ),
+            Self::InputTooLong { len, max } => {
+                write!(f, "input of length {len} exceeds the maximum of {max}")
+            }
+            Self::NotCharBoundary { offset } => {
+                write!(f, "byte offset {offset} is not a char boundary")
+            }
+            Self::NotHexPattern => write!(f, "bytes matched with a regex not compiled from hex"),
+        }
+    }
+}
+
+impl core::error::Error for MatchError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    JumpOutOfRange { pc: Pc, target: Pc },
+    MissingMatch,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::JumpOutOfRange { pc, target } => {
+                write!(f, "jump target {} at PC {} is out of range", target.0, pc.0)
+            }
+            Self::MissingMatch => write!(f, "missing match instruction"),
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+/// Which match the machine looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The first match found, preferring the first target of each split (leftmost-first).
+    First,
+    /// The longest match (leftmost-longest).
+    Longest,
+    /// A match that consumes the whole text.
+    Full,
+    /// No match, but the furthest string pointer that any thread reaches, to tell how far a failed match got.
+    Furthest,
+}
+
+/// Counters of the work done by runs of the machine, to find out why a pattern is slow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchStats {
+    /// Instructions executed.
+    pub instruction_count: usize,
+    /// `Split` instructions executed, each leaving a thread to backtrack to.
+    pub split_count: usize,
+    /// Most threads waiting to be backtracked to at once.
+    pub max_depth: usize,
+}
+
+/// Threads waiting to be backtracked to, kept across runs of the machine so that their stack is only allocated once.
+#[derive(Debug, Default)]
+pub struct Threads {
+    stack: Vec<(Pc, Sp)>,
+    // Iterations of the loops started by `LoopStart`, as the loop, the string pointer at which the iteration began,
+    // and the length of `stack` when it began, so that backtracking below that length drops it.
+    loops: Vec<(usize, Option<Sp>, usize)>,
+}
+
+impl Threads {
+    /// Threads with room for `capacity` of them before the stack grows.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            stack: Vec::with_capacity(capacity),
+            loops: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.stack.clear();
+        self.loops.clear();
+    }
 }
 
 /// Virtual machine for regular expression matching.
 #[derive(Debug)]
 pub struct Machine {
     instructions: Vec<Instruction>,
+    // Equality of a char of the pattern and a char of the text, used by `Char`.
+    char_eq: fn(char, char) -> bool,
+    // Whether `Range` also matches a char that `char_eq` finds equal to one of the range.
+    ranges_char_eq: bool,
 }
 
 impl Machine {
     pub fn new(instructions: Vec<Instruction>) -> Self {
-        Self { instructions }
+        Self {
+            instructions,
+            char_eq: |c, cc| c == cc,
+            ranges_char_eq: false,
+        }
     }
 
-    pub fn is_match(&self, text: &[char]) -> Result<bool, MatchError> {
-        self.is_matching(text, Pc(0), Sp(0))
+    /// The instructions the machine runs, where `Pc(n)` is the one at index `n`.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
     }
 
-    fn is_matching(&self, text: &[char], mut pc: Pc, mut sp: Sp) -> Result<bool, MatchError> {
-        loop {
-            let instruction = if let Some(i) = self.instructions.get(pc.0) {
-                i
+    /// Replace the equality used to compare a char of the pattern (first argument) with a char of the text.
+    pub fn with_char_eq(mut self, char_eq: fn(char, char) -> bool) -> Self {
+        self.char_eq = char_eq;
+        self
+    }
+
+    /// Also compare with the equality of [`Machine::with_char_eq`] in `Range`, which then matches a char equal to any
+    /// char of the range. The chars of the range are tried one by one, so a large range is slow to fail.
+    pub fn with_ranges_char_eq(mut self) -> Self {
+        self.ranges_char_eq = true;
+        self
+    }
+
+    /// Check that every jump target is an instruction of the program, and that the program can match at all.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let in_range = |pc: Pc, target: Pc| {
+            if target.0 < self.instructions.len() {
+                Ok(())
             } else {
-                return Err(MatchError::InstructionNotFound);
-            };
+                Err(ValidationError::JumpOutOfRange { pc, target })
+            }
+        };
 
+        for (pc, instruction) in self.instructions.iter().enumerate() {
             match *instruction {
-                Instruction::Char(c) => {
-                    let Some(cc) = text.get(sp.0) else {
-                        return Ok(false);
-                    };
-                    if c == *cc {
-                        pc.inc(|| MatchError::PcOverflow)?;
-                        sp.inc(|| MatchError::SpOverflow)?;
-                    } else {
-                        return Ok(false);
-                    }
-                }
-                Instruction::Match => return Ok(true),
-                Instruction::Jmp(new_pc) => pc = new_pc,
+                Instruction::Jmp(target)
+                | Instruction::LookAhead(target)
+                | Instruction::NegativeLookAhead(target)
+                | Instruction::LookBehind(target, _)
+                | Instruction::NegativeLookBehind(target, _) => in_range(Pc(pc), target)?,
                 Instruction::Split(l1, l2) => {
-                    if self.is_matching(text, l1, sp)? || self.is_matching(text, l2, sp)? {
-                        return Ok(true);
-                    } else {
-                        return Ok(false);
-                    }
+                    in_range(Pc(pc), l1)?;
+                    in_range(Pc(pc), l2)?;
+                }
+                Instruction::Char(_)
+                | Instruction::Range(..)
+                | Instruction::AsciiClass(_)
+                | Instruction::Match
+                | Instruction::AnyByte
+                | Instruction::AnyByteExceptNewline
+                | Instruction::SearchStart
+                | Instruction::LoopStart(..)
+                | Instruction::LoopCheck(_)
+                | Instruction::Fail => {}
+            }
+        }
+
+        if !self.instructions.contains(&Instruction::Match) {
+            return Err(ValidationError::MissingMatch);
+        }
+
+        Ok(())
+    }
+
+    /// PCs of the instructions that no thread can reach from PC 0, in order, e.g. a branch that an optimization cut
+    /// off. A target out of range is ignored, which [`Machine::validate`] reports.
+    pub fn unreachable_instructions(&self) -> Vec<Pc> {
+        let reached = codegen::reachable(&self.instructions);
+        (0..reached.len())
+            .filter(|&pc| !reached[pc])
+            .map(Pc)
+            .collect()
+    }
+
+    /// Run the program from `sp` and return the end of the match, reusing the stack of `threads`.
+    ///
+    /// `search_start` is where the search for a match began, which `SearchStart` asserts.
+    pub fn run(
+        &self,
+        threads: &mut Threads,
+        text: &[char],
+        search_start: Sp,
+        sp: Sp,
+        mode: Mode,
+    ) -> Result<Option<Sp>, MatchError> {
+        // A previous run that failed may have left threads behind.
+        threads.clear();
+        let mut stats = MatchStats::default();
+        self.matching_end::<false>(threads, text, search_start, (Pc(0), sp), mode, &mut stats)
+    }
+
+    /// Run the program as [`Machine::run`] does, and add the work done to `stats`.
+    pub fn run_with_stats(
+        &self,
+        threads: &mut Threads,
+        text: &[char],
+        search_start: Sp,
+        sp: Sp,
+        mode: Mode,
+        stats: &mut MatchStats,
+    ) -> Result<Option<Sp>, MatchError> {
+        threads.clear();
+        self.matching_end::<true>(threads, text, search_start, (Pc(0), sp), mode, stats)
+    }
+
+    /// Find the leftmost match that starts at or after `search_start`, and return its start and end.
+    ///
+    /// The program is run from each position in turn, and the match found from the first position that has one is
+    /// returned, so the matches of the given mode are leftmost among them.
+    pub fn find_span(
+        &self,
+        threads: &mut Threads,
+        text: &[char],
+        search_start: Sp,
+        mode: Mode,
+    ) -> Result<Option<(Sp, Sp)>, MatchError> {
+        for start in (search_start.0..=text.len()).map(Sp) {
+            if let Some(end) = self.run(threads, text, search_start, start, mode)? {
+                return Ok(Some((start, end)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Run the program from the `thread`, i.e. its `pc` and `sp`, and return the string pointer at which `Match` is
+    /// reached.
+    ///
+    /// With [`Mode::Longest`], both branches of every split are explored and the furthest end is returned.
+    /// With [`Mode::Full`], reaching `Match` before the end of the text counts as a failure.
+    /// With [`Mode::Furthest`], every thread is run to its end, and the furthest string pointer reached is returned.
+    ///
+    /// Backtracking uses an explicit stack of the threads left to try rather than recursion, so that a deep search
+    /// does not overflow the native stack. The threads are pushed on top of those of `threads`, which a lookaround
+    /// shares with the search around it, and are limited to [`MAX_DEPTH`].
+    ///
+    /// The work is counted in `stats` only if `STATS` is set, so that the other runs do not pay for it.
+    fn matching_end<const STATS: bool>(
+        &self,
+        threads: &mut Threads,
+        text: &[char],
+        search_start: Sp,
+        thread: (Pc, Sp),
+        mode: Mode,
+        stats: &mut MatchStats,
+    ) -> Result<Option<Sp>, MatchError> {
+        // Threads to backtrack to, the most recent split last. Those below `base` belong to the outer search.
+        let base = threads.stack.len();
+        let loops_base = threads.loops.len();
+        threads.stack.push(thread);
+        // Furthest end found so far (`Mode::Longest`), or furthest string pointer reached (`Mode::Furthest`).
+        let mut best = None;
+
+        'thread: while threads.stack.len() > base {
+            let Some((mut pc, mut sp)) = threads.stack.pop() else {
+                break;
+            };
+            // The iterations that began after the thread was pushed are not on its path.
+            let depth = threads.stack.len();
+            while threads.loops.last().is_some_and(|&(_, _, len)| len > depth) {
+                threads.loops.pop();
+            }
+            loop {
+                if mode == Mode::Furthest {
+                    best = best.max(Some(sp));
+                }
+                if STATS {
+                    stats.instruction_count += 1;
                 }
-                Instruction::AnyByte => {
-                    // The dot matches any character, but does not usually match an empty character.
-                    if text.get(sp.0).is_some() {
+                let instruction = if let Some(i) = self.instructions.get(pc.0) {
+                    i
+                } else {
+                    return Err(MatchError::InstructionNotFound);
+                };
+
+                match *instruction {
+                    Instruction::Char(c) => {
+                        let Some(cc) = text.get(sp.0) else {
+                            continue 'thread;
+                        };
+                        if (self.char_eq)(c, *cc) {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                            sp.inc(|| MatchError::SpOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::Range(lo, hi) => {
+                        let in_range = text.get(sp.0).is_some_and(|&c| {
+                            (lo..=hi).contains(&c)
+                                || self.ranges_char_eq && (lo..=hi).any(|cc| (self.char_eq)(cc, c))
+                        });
+                        if in_range {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                            sp.inc(|| MatchError::SpOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::AsciiClass(bits) => {
+                        let in_class = text.get(sp.0).is_some_and(|&c| {
+                            c.is_ascii() && bits[c as usize / 64] >> (c as usize % 64) & 1 == 1
+                        });
+                        if in_class {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                            sp.inc(|| MatchError::SpOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::Match => match mode {
+                        Mode::First => {
+                            threads.stack.truncate(base);
+                            threads.loops.truncate(loops_base);
+                            return Ok(Some(sp));
+                        }
+                        Mode::Longest => {
+                            best = best.max(Some(sp));
+                            continue 'thread;
+                        }
+                        Mode::Full if sp.0 == text.len() => {
+                            threads.stack.truncate(base);
+                            threads.loops.truncate(loops_base);
+                            return Ok(Some(sp));
+                        }
+                        Mode::Full | Mode::Furthest => continue 'thread,
+                    },
+                    Instruction::Jmp(new_pc) => pc = new_pc,
+                    Instruction::Split(l1, l2) => {
+                        if threads.stack.len() - base >= MAX_DEPTH {
+                            return Err(MatchError::DepthLimitExceeded { pc, sp });
+                        }
+                        // Try `l1` first, and come back to `l2` when it fails.
+                        threads.stack.push((l2, sp));
+                        if STATS {
+                            stats.split_count += 1;
+                            stats.max_depth = stats.max_depth.max(threads.stack.len());
+                        }
+                        pc = l1;
+                    }
+                    Instruction::SearchStart => {
+                        if sp == search_start {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::LookAhead(next) | Instruction::NegativeLookAhead(next) => {
+                        // Search from the next instruction to its own `Match`, without consuming the text.
+                        let mut sub_pc = pc;
+                        sub_pc.inc(|| MatchError::PcOverflow)?;
+                        let found = self
+                            .matching_end::<STATS>(
+                                threads,
+                                text,
+                                search_start,
+                                (sub_pc, sp),
+                                Mode::First,
+                                stats,
+                            )?
+                            .is_some();
+                        if found == matches!(instruction, Instruction::LookAhead(_)) {
+                            pc = next;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::LookBehind(next, width)
+                    | Instruction::NegativeLookBehind(next, width) => {
+                        // Search from the next instruction, `width` chars back. Every match of the operand is
+                        // `width` chars long, so it ends at the string pointer.
+                        let mut sub_pc = pc;
+                        sub_pc.inc(|| MatchError::PcOverflow)?;
+                        let found = match sp.0.checked_sub(width) {
+                            Some(start) => self
+                                .matching_end::<STATS>(
+                                    threads,
+                                    text,
+                                    search_start,
+                                    (sub_pc, Sp(start)),
+                                    Mode::First,
+                                    stats,
+                                )?
+                                .is_some(),
+                            None => false,
+                        };
+                        if found == matches!(instruction, Instruction::LookBehind(..)) {
+                            pc = next;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::LoopStart(id, first_may_be_empty) => {
+                        let start = (!first_may_be_empty).then_some(sp);
+                        threads.loops.push((id, start, threads.stack.len()));
+                        pc.inc(|| MatchError::PcOverflow)?;
+                    }
+                    Instruction::LoopCheck(id) => {
+                        let start = threads
+                            .loops
+                            .iter()
+                            .rev()
+                            .find(|&&(loop_id, ..)| loop_id == id)
+                            .and_then(|&(_, start, _)| start);
+                        if start == Some(sp) {
+                            continue 'thread;
+                        }
+                        // The next iteration begins where this one ended.
+                        threads.loops.push((id, Some(sp), threads.stack.len()));
                         pc.inc(|| MatchError::PcOverflow)?;
-                        sp.inc(|| MatchError::SpOverflow)?;
-                    } else {
-                        return Ok(false);
+                    }
+                    Instruction::Fail => continue 'thread,
+                    Instruction::AnyByte => {
+                        // The dot matches any character, but does not usually match an empty character.
+                        if text.get(sp.0).is_some() {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                            sp.inc(|| MatchError::SpOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
+                    }
+                    Instruction::AnyByteExceptNewline => {
+                        if text.get(sp.0).is_some_and(|&c| c != '\n') {
+                            pc.inc(|| MatchError::PcOverflow)?;
+                            sp.inc(|| MatchError::SpOverflow)?;
+                        } else {
+                            continue 'thread;
+                        }
                     }
                 }
             }
         }
+
+        threads.loops.truncate(loops_base);
+        Ok(best)
+    }
+}
+
+// `Regex` matches through `Machine::run` with the threads of a `Matcher`, so these are only shorthands for the tests.
+#[cfg(test)]
+impl Machine {
+    pub fn is_match(&self, text: &[char]) -> Result<bool, MatchError> {
+        Ok(self.match_end(text, Sp(0), Sp(0))?.is_some())
+    }
+
+    /// Check if the whole text matches, not only a prefix of it.
+    pub fn is_full_match(&self, text: &[char]) -> Result<bool, MatchError> {
+        self.is_full_match_at(text, Sp(0), Sp(0))
+    }
+
+    /// Check if the text from `sp` to the end matches.
+    pub fn is_full_match_at(
+        &self,
+        text: &[char],
+        search_start: Sp,
+        sp: Sp,
+    ) -> Result<bool, MatchError> {
+        Ok(self
+            .run(&mut Threads::default(), text, search_start, sp, Mode::Full)?
+            .is_some())
+    }
+
+    /// Find the end of the match starting at `sp`, preferring the first alternative of each split (leftmost-first).
+    ///
+    /// `search_start` is where the search for a match began, which `SearchStart` asserts.
+    pub fn match_end(
+        &self,
+        text: &[char],
+        search_start: Sp,
+        sp: Sp,
+    ) -> Result<Option<Sp>, MatchError> {
+        self.run(&mut Threads::default(), text, search_start, sp, Mode::First)
+    }
+
+    /// Find the end of the longest match starting at `sp` (leftmost-longest).
+    pub fn longest_match_end(
+        &self,
+        text: &[char],
+        search_start: Sp,
+        sp: Sp,
+    ) -> Result<Option<Sp>, MatchError> {
+        self.run(
+            &mut Threads::default(),
+            text,
+            search_start,
+            sp,
+            Mode::Longest,
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
+    use alloc::vec;
+
     use super::*;
 
     macro_rules! chars {
@@ -96,6 +557,83 @@ mod test {
         };
     }
 
+    #[test]
+    fn validate() {
+        // a|b
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(3)),
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Jmp(Pc(4)),
+            /*   :3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Match,
+        ]);
+        assert_eq!(machine.validate(), Ok(()));
+
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::Jmp(Pc(3)),
+            /*   :2 */ Instruction::Match,
+        ]);
+        assert_eq!(
+            machine.validate(),
+            Err(ValidationError::JumpOutOfRange {
+                pc: Pc(1),
+                target: Pc(3)
+            })
+        );
+
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(5)),
+            /*   :1 */ Instruction::Match,
+        ]);
+        assert_eq!(
+            machine.validate(),
+            Err(ValidationError::JumpOutOfRange {
+                pc: Pc(0),
+                target: Pc(5)
+            })
+        );
+
+        let machine = Machine::new(vec![Instruction::Char('a'), Instruction::AnyByte]);
+        assert_eq!(machine.validate(), Err(ValidationError::MissingMatch));
+    }
+
+    #[test]
+    fn unreachable_instructions() {
+        // a|b, where the jmp skips a dead block before the match.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(3)),
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Jmp(Pc(6)),
+            /*   :3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Jmp(Pc(6)),
+            /*   :5 */ Instruction::Char('c'),
+            /*   :6 */ Instruction::Match,
+            /*   :7 */ Instruction::Char('d'),
+        ]);
+        assert_eq!(machine.unreachable_instructions(), [Pc(5), Pc(7)]);
+
+        // (?=a)b, whose lookahead runs the instructions after it.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LookAhead(Pc(3)),
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Match,
+            /*   :3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Match,
+        ]);
+        assert!(machine.unreachable_instructions().is_empty());
+
+        // Nothing is reached after a fail, and a loop is walked once.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(2)),
+            /*   :1 */ Instruction::Jmp(Pc(0)),
+            /*   :2 */ Instruction::Fail,
+            /*   :3 */ Instruction::Match,
+        ]);
+        assert_eq!(machine.unreachable_instructions(), [Pc(3)]);
+        assert!(Machine::new(vec![]).unreachable_instructions().is_empty());
+    }
+
     #[test]
     fn concat() {
         let machine = Machine::new(vec![
@@ -205,5 +743,405 @@ mod test {
         assert!(machine.is_match(chars!("ayb")).unwrap());
         assert!(!machine.is_match(chars!("ab")).unwrap());
         assert!(!machine.is_match(chars!("")).unwrap());
+
+        // a.b, where `.` does not match `\n`
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::AnyByteExceptNewline,
+            /*   :2 */ Instruction::Char('b'),
+            /*   :3 */ Instruction::Match,
+        ]);
+        assert!(machine.is_match(chars!("axb")).unwrap());
+        assert!(!machine.is_match(chars!("a\nb")).unwrap());
+        assert!(!machine.is_match(chars!("ab")).unwrap());
+    }
+
+    #[test]
+    fn group_plus() {
+        // (ab)+c
+        let machine = Machine::new(vec![
+            /* L1:0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::Char('b'),
+            /*   :2 */ Instruction::Split(Pc(0), Pc(3)), // L1, L2
+            /* L2:3 */ Instruction::Char('c'),
+            /*   :4 */ Instruction::Match,
+        ]);
+        assert!(machine.is_match(chars!("abc")).unwrap());
+        assert!(machine.is_match(chars!("ababc")).unwrap());
+        assert!(!machine.is_match(chars!("abac")).unwrap());
+        assert!(!machine.is_match(chars!("abbc")).unwrap());
+        assert!(!machine.is_match(chars!("")).unwrap());
+    }
+
+    #[test]
+    fn is_full_match() {
+        // a+b?
+        let machine = Machine::new(vec![
+            /* L1:0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::Split(Pc(0), Pc(2)), // L1, L2
+            /* L2:2 */ Instruction::Split(Pc(3), Pc(4)), // L3, L4
+            /* L3:3 */ Instruction::Char('b'),
+            /* L4:4 */ Instruction::Match,
+        ]);
+        assert!(machine.is_full_match(chars!("a")).unwrap());
+        assert!(machine.is_full_match(chars!("aaab")).unwrap());
+        assert!(!machine.is_full_match(chars!("aaabb")).unwrap());
+        assert!(!machine.is_full_match(chars!("b")).unwrap());
+        assert!(!machine.is_full_match(chars!("")).unwrap());
+        assert!(machine.is_match(chars!("aaabb")).unwrap());
+    }
+
+    #[test]
+    fn match_end() {
+        // a|ab
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(3)), // L1, L2
+            /* L1:1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Jmp(Pc(5)), // L3
+            /* L2:3 */ Instruction::Char('a'),
+            /*   :4 */ Instruction::Char('b'),
+            /* L3:5 */ Instruction::Match,
+        ]);
+        assert_eq!(
+            machine.match_end(chars!("ab"), Sp(0), Sp(0)).unwrap(),
+            Some(Sp(1))
+        );
+        assert_eq!(
+            machine
+                .longest_match_end(chars!("ab"), Sp(0), Sp(0))
+                .unwrap(),
+            Some(Sp(2))
+        );
+        assert_eq!(
+            machine.match_end(chars!("xab"), Sp(1), Sp(1)).unwrap(),
+            Some(Sp(2))
+        );
+        assert_eq!(
+            machine
+                .longest_match_end(chars!("xab"), Sp(1), Sp(1))
+                .unwrap(),
+            Some(Sp(3))
+        );
+        assert_eq!(
+            machine.match_end(chars!("xab"), Sp(0), Sp(0)).unwrap(),
+            None
+        );
+        assert_eq!(
+            machine
+                .longest_match_end(chars!("b"), Sp(0), Sp(0))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        // (a*)*b
+        let machine = Machine::new(vec![
+            /* L1:0 */ Instruction::Split(Pc(1), Pc(5)), // L2, L3
+            /* L2:1 */ Instruction::Split(Pc(2), Pc(4)), // L4, L5
+            /* L4:2 */ Instruction::Char('a'),
+            /*   :3 */ Instruction::Jmp(Pc(1)), // L2
+            /* L5:4 */ Instruction::Jmp(Pc(0)), // L1
+            /* L3:5 */ Instruction::Char('b'),
+            /*   :6 */ Instruction::Match,
+        ]);
+        // The inner star matches the empty string, so the outer one loops forever without consuming the text.
+        assert_eq!(
+            machine.is_match(chars!("")),
+            Err(MatchError::DepthLimitExceeded {
+                pc: Pc(1),
+                sp: Sp(0)
+            })
+        );
+        assert!(matches!(
+            machine.is_match(chars!("aa")),
+            Err(MatchError::DepthLimitExceeded { pc: Pc(1), .. })
+        ));
+
+        // A loop that consumes the text only needs one thread per char.
+        // a*
+        let machine = Machine::new(vec![
+            /* L1:0 */ Instruction::Split(Pc(1), Pc(3)), // L2, L3
+            /* L2:1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Jmp(Pc(0)), // L1
+            /* L3:3 */ Instruction::Match,
+        ]);
+        let text = "a".repeat(MAX_DEPTH - 1);
+        assert!(machine.is_full_match(chars!(text)).unwrap());
+        let text = "a".repeat(MAX_DEPTH);
+        assert_eq!(
+            machine.is_full_match(chars!(text)),
+            Err(MatchError::DepthLimitExceeded {
+                pc: Pc(0),
+                sp: Sp(MAX_DEPTH)
+            })
+        );
+    }
+
+    #[test]
+    fn loop_check() {
+        // (a*)*b, whose outer loop stops an iteration that consumes nothing.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LoopStart(0, false),
+            /* L1:1 */ Instruction::Split(Pc(2), Pc(7)), // L2, L3
+            /* L2:2 */ Instruction::Split(Pc(3), Pc(5)), // L4, L5
+            /* L4:3 */ Instruction::Char('a'),
+            /*   :4 */ Instruction::Jmp(Pc(2)), // L2
+            /* L5:5 */ Instruction::LoopCheck(0),
+            /*   :6 */ Instruction::Jmp(Pc(1)), // L1
+            /* L3:7 */ Instruction::Char('b'),
+            /*   :8 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        assert!(!machine.is_match(chars!("")).unwrap());
+        assert!(!machine.is_match(chars!("aa")).unwrap());
+        assert!(machine.is_full_match(chars!("aab")).unwrap());
+        assert!(machine.is_full_match(chars!("b")).unwrap());
+
+        // (\G)+, whose first iteration may consume nothing.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LoopStart(0, true),
+            /* L1:1 */ Instruction::SearchStart,
+            /*   :2 */ Instruction::LoopCheck(0),
+            /*   :3 */ Instruction::Split(Pc(1), Pc(4)), // L1, L2
+            /* L2:4 */ Instruction::Match,
+        ]);
+        assert_eq!(
+            machine.match_end(chars!("a"), Sp(0), Sp(0)),
+            Ok(Some(Sp(0)))
+        );
+        assert_eq!(machine.match_end(chars!("a"), Sp(0), Sp(1)), Ok(None));
+    }
+
+    #[test]
+    fn search_start() {
+        // \Gab
+        let machine = Machine::new(vec![
+            Instruction::SearchStart,
+            Instruction::Char('a'),
+            Instruction::Char('b'),
+            Instruction::Match,
+        ]);
+        assert_eq!(
+            machine.match_end(chars!("xab"), Sp(1), Sp(1)).unwrap(),
+            Some(Sp(3))
+        );
+        assert_eq!(
+            machine.match_end(chars!("xab"), Sp(0), Sp(1)).unwrap(),
+            None
+        );
+        assert!(machine.is_match(chars!("ab")).unwrap());
+    }
+
+    #[test]
+    fn char_eq() {
+        // ab
+        let machine = Machine::new(vec![
+            Instruction::Char('a'),
+            Instruction::Char('b'),
+            Instruction::Match,
+        ])
+        .with_char_eq(|c, cc| c.eq_ignore_ascii_case(&cc));
+        assert!(machine.is_match(chars!("aB")).unwrap());
+        assert!(machine.is_match(chars!("AB")).unwrap());
+        assert!(!machine.is_match(chars!("AC")).unwrap());
+
+        // [a-c]
+        let machine = Machine::new(vec![Instruction::Range('a', 'c'), Instruction::Match])
+            .with_char_eq(|c, cc| c.eq_ignore_ascii_case(&cc));
+        assert!(!machine.is_match(chars!("B")).unwrap());
+        let machine = machine.with_ranges_char_eq();
+        assert!(machine.is_match(chars!("B")).unwrap());
+        assert!(machine.is_match(chars!("b")).unwrap());
+        assert!(!machine.is_match(chars!("D")).unwrap());
+    }
+
+    #[test]
+    fn fail() {
+        let machine = Machine::new(vec![Instruction::Fail, Instruction::Match]);
+        assert!(!machine.is_match(chars!("")).unwrap());
+        assert!(!machine.is_match(chars!("a")).unwrap());
+
+        // A failing branch backtracks to the next one.
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Split(Pc(1), Pc(2)), // L1, L2
+            /* L1:1 */ Instruction::Fail,
+            /* L2:2 */ Instruction::Char('b'),
+            /*   :3 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        assert!(machine.is_match(chars!("b")).unwrap());
+        assert!(!machine.is_match(chars!("a")).unwrap());
+        assert_eq!(
+            machine
+                .longest_match_end(chars!("b"), Sp(0), Sp(0))
+                .unwrap(),
+            Some(Sp(1))
+        );
+    }
+
+    #[test]
+    fn look_ahead() {
+        // (?=.*1).+
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LookAhead(Pc(6)), // L1
+            /* L2:1 */ Instruction::Split(Pc(2), Pc(4)), // L3, L4
+            /* L3:2 */ Instruction::AnyByte,
+            /*   :3 */ Instruction::Jmp(Pc(1)), // L2
+            /* L4:4 */ Instruction::Char('1'),
+            /*   :5 */ Instruction::Match,
+            /* L1:6 */ Instruction::AnyByte,
+            /* L5:7 */ Instruction::Split(Pc(6), Pc(8)), // L1, L6
+            /* L6:8 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        assert!(machine.is_full_match(chars!("a1b")).unwrap());
+        assert!(!machine.is_full_match(chars!("abc")).unwrap());
+        // The lookahead does not consume the text.
+        assert_eq!(
+            machine
+                .longest_match_end(chars!("a1b"), Sp(0), Sp(0))
+                .unwrap(),
+            Some(Sp(3))
+        );
+
+        // a(?!b)
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::NegativeLookAhead(Pc(4)), // L1
+            /*   :2 */ Instruction::Char('b'),
+            /*   :3 */ Instruction::Match,
+            /* L1:4 */ Instruction::Match,
+        ]);
+        assert!(machine.is_match(chars!("ac")).unwrap());
+        assert!(machine.is_match(chars!("a")).unwrap());
+        assert!(!machine.is_match(chars!("ab")).unwrap());
+    }
+
+    #[test]
+    fn look_behind() {
+        // (?<=a)b
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LookBehind(Pc(3), 1), // L1
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Match,
+            /* L1:3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        assert_eq!(
+            machine.match_end(chars!("ab"), Sp(0), Sp(1)).unwrap(),
+            Some(Sp(2))
+        );
+        assert_eq!(machine.match_end(chars!("cb"), Sp(0), Sp(1)).unwrap(), None);
+        // There is no char before the start of the text.
+        assert_eq!(machine.match_end(chars!("b"), Sp(0), Sp(0)).unwrap(), None);
+
+        // (?<!a)b
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::NegativeLookBehind(Pc(3), 1), // L1
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Match,
+            /* L1:3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Match,
+        ]);
+        assert_eq!(machine.match_end(chars!("ab"), Sp(0), Sp(1)).unwrap(), None);
+        assert_eq!(
+            machine.match_end(chars!("b"), Sp(0), Sp(0)).unwrap(),
+            Some(Sp(1))
+        );
+    }
+
+    #[test]
+    fn quantified_empty_group() {
+        // a()?b
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Char('a'),
+            /*   :1 */ Instruction::Split(Pc(2), Pc(2)), // L1, L1
+            /* L1:2 */ Instruction::Char('b'),
+            /*   :3 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        assert!(machine.is_full_match(chars!("ab")).unwrap());
+        assert!(!machine.is_match(chars!("b")).unwrap());
+        assert!(!machine.is_match(chars!("a")).unwrap());
+    }
+
+    #[test]
+    fn find_span() {
+        // (?<=a)b+(?!c)
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::LookBehind(Pc(3), 1), // L1
+            /*   :1 */ Instruction::Char('a'),
+            /*   :2 */ Instruction::Match,
+            /* L1:3 */ Instruction::Char('b'),
+            /*   :4 */ Instruction::Split(Pc(3), Pc(5)), // L1, L2
+            /* L2:5 */ Instruction::NegativeLookAhead(Pc(8)), // L3
+            /*   :6 */ Instruction::Char('c'),
+            /*   :7 */ Instruction::Match,
+            /* L3:8 */ Instruction::Match,
+        ]);
+        assert!(machine.validate().is_ok());
+        let mut threads = Threads::default();
+        let text = chars!("bbabbcabbb");
+        assert_eq!(
+            machine
+                .find_span(&mut threads, text, Sp(0), Mode::First)
+                .unwrap(),
+            // `b+` gives back the last `b` of `abbc` so that `c` does not follow.
+            Some((Sp(3), Sp(4)))
+        );
+        assert_eq!(
+            machine
+                .find_span(&mut threads, text, Sp(5), Mode::First)
+                .unwrap(),
+            Some((Sp(7), Sp(10)))
+        );
+        assert_eq!(
+            machine
+                .find_span(&mut threads, text, Sp(0), Mode::Full)
+                .unwrap(),
+            Some((Sp(7), Sp(10)))
+        );
+        assert_eq!(
+            machine
+                .find_span(&mut threads, chars!("bb"), Sp(0), Mode::First)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn range() {
+        // [😀-😏]
+        let machine = Machine::new(vec![
+            /*   :0 */ Instruction::Range('😀', '😏'),
+            /*   :1 */ Instruction::Match,
+        ]);
+        assert!(machine.is_full_match(chars!("😀")).unwrap());
+        assert!(machine.is_full_match(chars!("😏")).unwrap());
+        assert!(!machine.is_match(chars!("😐")).unwrap());
+        assert!(!machine.is_match(chars!("a")).unwrap());
+        assert!(!machine.is_match(chars!("")).unwrap());
+    }
+
+    #[test]
+    fn ascii_class() {
+        // [0a\x7f]
+        let machine = Machine::new(vec![
+            /*   :0 */
+            Instruction::AsciiClass([1 << b'0', 1 << (b'a' - 64) | 1 << (0x7f - 64)]),
+            /*   :1 */ Instruction::Match,
+        ]);
+        assert!(machine.is_full_match(chars!("0")).unwrap());
+        assert!(machine.is_full_match(chars!("a")).unwrap());
+        assert!(machine.is_full_match(chars!("\x7f")).unwrap());
+        assert!(!machine.is_match(chars!("1")).unwrap());
+        assert!(!machine.is_match(chars!("A")).unwrap());
+        // Chars past ASCII have no bit.
+        assert!(!machine.is_match(chars!("\u{80}")).unwrap());
+        assert!(!machine.is_match(chars!("á")).unwrap());
+        assert!(!machine.is_match(chars!("")).unwrap());
     }
 }
@@ -0,0 +1,355 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::mem;
+
+use crate::parser::Ast;
+
+/// Remove redundant constructs of the AST without changing its matches:
+///
+/// - Nested sequences are flattened, e.g. `(ab)c` into `abc`, and empty strings are dropped from them.
+/// - A sequence of a single element is replaced with the element.
+/// - Stacked quantifiers are applied once, e.g. `a??` becomes `a?`, `a++` becomes `a+`, and `a*?`, `a+?`, `a?+`,
+///   `a+*` and `a**` all become `a*`.
+/// - A duplicate of the previous alternative is removed, e.g. `a|a|b` becomes `a|b`, since it can only match where the
+///   previous one already failed.
+/// - An empty last alternative makes the others optional, e.g. `a|b|()` becomes `(a|b)?`. An empty alternative
+///   elsewhere is kept, since it is tried before the ones after it, e.g. `()|a` prefers the empty string.
+/// - The operand of `*` or `+` is rewritten not to match the empty string where that is simple, e.g. `(a?|b)*`
+///   becomes `(a|b)*`, so that the loop needs no check that it consumes a char. A sequence such as `(a?b?)*` or an
+///   assertion such as `\G` is kept, and the machine stops its iterations that consume nothing.
+pub fn simplify(ast: Ast) -> Ast {
+    match ast {
+        Ast::Concat(concat) => {
+            let mut elements = Vec::with_capacity(concat.len());
+            for element in concat.into_iter().map(simplify) {
+                match element {
+                    Ast::Concat(inner) => elements.extend(inner),
+                    Ast::Empty => {}
+                    _ => elements.push(element),
+                }
+            }
+            self::concat(elements)
+        }
+        alternation @ Ast::Or(..) => {
+            let mut operands = alternation
+                .into_or_operands()
+                .into_iter()
+                .map(simplify)
+                .collect::<Vec<_>>();
+            operands.dedup();
+            if operands.len() > 1 && operands.last() == Some(&Ast::Empty) {
+                operands.pop();
+                return question(or(operands));
+            }
+            or(operands)
+        }
+        Ast::Question(e) => question(simplify(*e)),
+        Ast::Star(e) => match non_empty(simplify(*e)) {
+            Some(e) => star(e),
+            None => Ast::Empty,
+        },
+        // An operand matching the empty string can be repeated zero times, so it is the same as `*` then.
+        Ast::Plus(e) => match simplify(*e) {
+            e @ Ast::Plus(_) => e,
+            e if e.min_len() > 0 || has_assertion(&e) => Ast::Plus(e.into()),
+            e => match non_empty(e) {
+                Some(e) => star(e),
+                None => Ast::Empty,
+            },
+        },
+        Ast::LookAhead(e) => Ast::LookAhead(simplify(*e).into()),
+        Ast::NegativeLookAhead(e) => Ast::NegativeLookAhead(simplify(*e).into()),
+        Ast::LookBehind(e, width) => Ast::LookBehind(simplify(*e).into(), width),
+        Ast::NegativeLookBehind(e, width) => Ast::NegativeLookBehind(simplify(*e).into(), width),
+        Ast::Char(_)
+        | Ast::Dot
+        | Ast::ScopedDot(_)
+        | Ast::Range(..)
+        | Ast::SearchStart
+        | Ast::Empty => ast,
+    }
+}
+
+/// `?` of the operand, where `(e+)?` is `e*`.
+fn question(e: Ast) -> Ast {
+    match e {
+        // Repeating the empty string, e.g. `()?`, still matches only the empty string.
+        e @ (Ast::Question(_) | Ast::Star(_) | Ast::Empty) => e,
+        Ast::Plus(e) => Ast::Star(e),
+        e => Ast::Question(e.into()),
+    }
+}
+
+/// `*` of the operand, where `(e+)*` is `e*`.
+fn star(e: Ast) -> Ast {
+    match e {
+        Ast::Plus(e) => Ast::Star(e),
+        e => Ast::Star(e.into()),
+    }
+}
+
+fn has_assertion(ast: &Ast) -> bool {
+    let mut found = false;
+    ast.walk(&mut |ast| {
+        found |= matches!(
+            ast,
+            Ast::SearchStart
+                | Ast::LookAhead(_)
+                | Ast::NegativeLookAhead(_)
+                | Ast::LookBehind(..)
+                | Ast::NegativeLookBehind(..)
+        );
+    });
+    found
+}
+
+/// Operand of a loop that matches the non-empty strings that the AST matches, trying them in the same order, or `None`
+/// if it only matches the empty string, e.g. `a|b` for `a?|b`. A sequence or an assertion may still match the empty
+/// string, which the loop then checks at run time.
+fn non_empty(ast: Ast) -> Option<Ast> {
+    if ast.min_len() > 0 {
+        return Some(ast);
+    }
+    match ast {
+        Ast::Empty => None,
+        Ast::Question(e) => non_empty(*e),
+        Ast::Star(e) | Ast::Plus(e) => non_empty(*e).map(|e| Ast::Plus(e.into())),
+        alternation @ Ast::Or(..) => {
+            let operands = alternation
+                .into_or_operands()
+                .into_iter()
+                .filter_map(non_empty)
+                .collect::<Vec<_>>();
+            (!operands.is_empty()).then(|| or(operands))
+        }
+        Ast::Concat(_)
+        | Ast::Char(_)
+        | Ast::Dot
+        | Ast::ScopedDot(_)
+        | Ast::Range(..)
+        | Ast::SearchStart
+        | Ast::LookAhead(_)
+        | Ast::NegativeLookAhead(_)
+        | Ast::LookBehind(..)
+        | Ast::NegativeLookBehind(..) => Some(ast),
+    }
+}
+
+/// Factor out the literal prefix shared by consecutive operands of alternations, e.g. `abc|abd` into `ab(c|d)`.
+///
+/// Only consecutive operands are merged, so the alternatives are still tried in the same order and the matches are
+/// unchanged. An operand that is entirely the prefix is left empty, e.g. `ab|abc` becomes `ab(|c)`.
+pub fn factor_prefixes(ast: Ast) -> Ast {
+    match ast {
+        Ast::Concat(concat) => Ast::Concat(concat.into_iter().map(factor_prefixes).collect()),
+        alternation @ Ast::Or(..) => {
+            let operands = alternation
+                .into_or_operands()
+                .into_iter()
+                .map(factor_prefixes)
+                .collect();
+            or(factor_operands(operands))
+        }
+        Ast::Question(e) => Ast::Question(factor_prefixes(*e).into()),
+        Ast::Star(e) => Ast::Star(factor_prefixes(*e).into()),
+        Ast::Plus(e) => Ast::Plus(factor_prefixes(*e).into()),
+        Ast::LookAhead(e) => Ast::LookAhead(factor_prefixes(*e).into()),
+        Ast::NegativeLookAhead(e) => Ast::NegativeLookAhead(factor_prefixes(*e).into()),
+        Ast::LookBehind(e, width) => Ast::LookBehind(factor_prefixes(*e).into(), width),
+        Ast::NegativeLookBehind(e, width) => {
+            Ast::NegativeLookBehind(factor_prefixes(*e).into(), width)
+        }
+        Ast::Char(_)
+        | Ast::Dot
+        | Ast::ScopedDot(_)
+        | Ast::Range(..)
+        | Ast::SearchStart
+        | Ast::Empty => ast,
+    }
+}
+
+/// Merge the runs of consecutive operands that start with the same literal.
+fn factor_operands(operands: Vec<Ast>) -> Vec<Ast> {
+    let mut factored = Vec::with_capacity(operands.len());
+    let mut run: Vec<Vec<Ast>> = Vec::new();
+
+    for operand in operands {
+        let operand = elements(operand);
+        if !run.is_empty() && first_char(&run[0]).map_or(true, |c| first_char(&operand) != Some(c))
+        {
+            factored.extend(factor_run(mem::take(&mut run)));
+        }
+        run.push(operand);
+    }
+    if !run.is_empty() {
+        factored.extend(factor_run(run));
+    }
+
+    factored
+}
+
+/// Factor the common literal prefix out of operands that start with the same literal, and return the operands
+/// that replace them.
+fn factor_run(run: Vec<Vec<Ast>>) -> Vec<Ast> {
+    if run.len() < 2 {
+        return run.into_iter().map(concat).collect();
+    }
+
+    let max = run.iter().map(Vec::len).min().unwrap_or(0);
+    let len = (0..max)
+        .take_while(|&i| {
+            matches!(run[0][i], Ast::Char(_))
+                && run[1..].iter().all(|operand| operand[i] == run[0][i])
+        })
+        .count();
+    if len == 0 {
+        return run.into_iter().map(concat).collect();
+    }
+
+    let mut prefix = Vec::new();
+    let mut rests = Vec::with_capacity(run.len());
+    for mut operand in run {
+        let rest = operand.split_off(len);
+        if prefix.is_empty() {
+            prefix = operand;
+        }
+        rests.push(concat(rest));
+    }
+    prefix.push(or(factor_operands(rests)));
+    vec![concat(prefix)]
+}
+
+/// Split an operand into the sequence of its elements.
+fn elements(ast: Ast) -> Vec<Ast> {
+    match ast {
+        Ast::Concat(concat) => concat,
+        _ => vec![ast],
+    }
+}
+
+fn first_char(elements: &[Ast]) -> Option<char> {
+    match elements.first() {
+        Some(Ast::Char(c)) => Some(*c),
+        _ => None,
+    }
+}
+
+/// Construct an AST for the sequence, without wrapping a single element.
+fn concat(mut elements: Vec<Ast>) -> Ast {
+    match elements.len() {
+        0 => Ast::Empty,
+        1 => elements.pop().unwrap(),
+        _ => Ast::Concat(elements),
+    }
+}
+
+/// Construct a right-nested alternation of the operands.
+fn or(mut operands: Vec<Ast>) -> Ast {
+    let last = operands.pop().expect("an alternation has operands");
+    operands.into_iter().rev().fold(last, |ast, operand| {
+        Ast::Or(Box::new(operand), Box::new(ast))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use crate::{
+        codegen::generate_code,
+        parser::{parse, Ast},
+    };
+
+    #[test]
+    fn simplify() {
+        let simplify = |pattern| super::simplify(parse(pattern).unwrap());
+
+        assert_eq!(simplify("(ab)c"), parse("abc").unwrap());
+        assert_eq!(simplify("x((ab)(cd))+"), parse("x(abcd)+").unwrap());
+        assert_eq!(simplify("a??"), parse("a?").unwrap());
+        // An empty last alternative is `?`, but not an empty one before others.
+        assert_eq!(simplify("(a|())"), parse("a?").unwrap());
+        assert_eq!(simplify("a|bc|()"), parse("(a|bc)?").unwrap());
+        assert_eq!(simplify("a|b*|()"), parse("(a|b*)?").unwrap());
+        assert_eq!(simplify("a+|()|()"), parse("a*").unwrap());
+        assert_eq!(simplify("()|a"), parse("()|a").unwrap());
+        assert_eq!(simplify("a|()|b"), parse("a|()|b").unwrap());
+        // Stacked quantifiers are applied once.
+        for pattern in [
+            "a**", "a*?", "a+?", "a?+", "a?*", "a+*", "a*+", "(a*)*", "((a+)?)+",
+        ] {
+            assert_eq!(simplify(pattern), parse("a*").unwrap(), "{pattern}");
+        }
+        assert_eq!(simplify("a++"), parse("a+").unwrap());
+        assert_eq!(simplify("(ab+)*"), parse("(ab+)*").unwrap());
+        assert_eq!(simplify("(ab?)??"), parse("(ab?)?").unwrap());
+        assert_eq!(simplify("a|a"), parse("a").unwrap());
+        assert_eq!(simplify("a|(a)|b|a"), parse("a|b|a").unwrap());
+        assert_eq!(simplify("(ab)c|abc"), parse("abc").unwrap());
+        assert_eq!(simplify(""), Ast::Empty);
+        assert_eq!(simplify("a()?b"), parse("ab").unwrap());
+        assert_eq!(simplify("()*|(())+"), Ast::Empty);
+
+        // The operand of a loop does not match the empty string, but for a sequence or an assertion.
+        assert_eq!(simplify("(a?)*"), parse("a*").unwrap());
+        assert_eq!(simplify("(a*)+"), parse("a*").unwrap());
+        assert_eq!(simplify("(a|b?)*"), parse("(a|b)*").unwrap());
+        assert_eq!(simplify("(a?b?)*c"), parse("(a?b?)*c").unwrap());
+        assert_eq!(simplify("(a?)+"), parse("a*").unwrap());
+        assert_eq!(simplify("(a+)+"), parse("a+").unwrap());
+        assert_eq!(simplify("((a?)?)*"), parse("a*").unwrap());
+        assert_eq!(simplify("(\\G|a)*"), parse("(\\G|a)*").unwrap());
+        assert_eq!(simplify("(\\G|a?)+"), parse("(\\G|a?)+").unwrap());
+
+        let naive = generate_code(parse("(a|a)??").unwrap(), true, false).unwrap();
+        let simplified = generate_code(simplify("(a|a)??"), true, false).unwrap();
+        assert_eq!(
+            simplified,
+            generate_code(parse("a?").unwrap(), true, false).unwrap()
+        );
+        assert!(simplified.len() < naive.len());
+    }
+
+    #[test]
+    fn factor_prefixes() {
+        let factor = |pattern| super::factor_prefixes(parse(pattern).unwrap());
+
+        assert_eq!(factor("abc|abd"), parse("ab(c|d)").unwrap());
+        assert_eq!(
+            factor("abc|abd|x|ay|az"),
+            parse("ab(c|d)|x|a(y|z)").unwrap()
+        );
+        assert_eq!(factor("abc|abd|abe"), parse("ab(c|d|e)").unwrap());
+        // Only consecutive operands are merged, to keep the order of the alternatives.
+        assert_eq!(factor("ab|x|ac"), parse("ab|x|ac").unwrap());
+        // An operand can be left empty.
+        let ast = Ast::Concat(vec![
+            Ast::Char('a'),
+            Ast::Char('b'),
+            Ast::Or(Ast::Empty.into(), Ast::Char('c').into()),
+        ]);
+        assert_eq!(factor("ab|abc"), ast);
+        let ast = Ast::Concat(vec![
+            Ast::Char('a'),
+            Ast::Char('b'),
+            Ast::Or(
+                Ast::Concat(vec![
+                    Ast::Char('c'),
+                    Ast::Or(Ast::Empty.into(), Ast::Char('d').into()),
+                ])
+                .into(),
+                Ast::Char('d').into(),
+            ),
+        ]);
+        assert_eq!(factor("abc|abcd|abd"), ast);
+        // Nested alternations are factored too.
+        assert_eq!(
+            factor("x(foobar|foobaz)*"),
+            parse("x(fooba(r|z))*").unwrap()
+        );
+        assert_eq!(factor(".a|.a"), parse(".a|.a").unwrap());
+
+        let naive = generate_code(parse("(abc|abd)").unwrap(), true, false).unwrap();
+        let optimized = generate_code(factor("(abc|abd)"), true, false).unwrap();
+        assert!(optimized.len() < naive.len());
+    }
+}
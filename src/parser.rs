@@ -1,32 +1,555 @@
-use std::mem;
-
-use thiserror::Error;
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::{
+    fmt,
+    iter::{self, Peekable},
+    mem,
+    str::Chars,
+};
 
+/// Abstract syntax tree of a regular expression, as returned by [`parse`].
 #[derive(Debug, PartialEq, Eq)]
 pub enum Ast {
+    /// Literal character, e.g. `a`.
     Char(char),
+    /// Sequence, e.g. `ab`.
     Concat(Vec<Ast>),
+    /// Alternation, e.g. `a|b`. A chain `a|b|c` is nested to the right.
     Or(Box<Ast>, Box<Ast>),
+    /// Zero or one, e.g. `a?`.
     Question(Box<Ast>),
+    /// Zero or more, e.g. `a*`.
     Star(Box<Ast>),
+    /// One or more, e.g. `a+`.
     Plus(Box<Ast>),
+    /// Any character, `.`.
     Dot,
+    /// Any character under an inline `s` flag, e.g. `.` in `(?s:.)` or `(?-s:.)`, which matches `\n` if the flag is
+    /// set, rather than as the program is compiled for `.`.
+    ScopedDot(bool),
+    /// Character within an inclusive range of code points, e.g. `a-z` in `[a-z]`.
+    Range(char, char),
+    /// Position where the search began, `\G`.
+    SearchStart,
+    /// Empty string, which is what an empty pattern matches.
+    Empty,
+    /// Positive lookahead, e.g. `(?=a)`, which asserts that the operand matches at the position without consuming it.
+    LookAhead(Box<Ast>),
+    /// Negative lookahead, e.g. `(?!a)`, which asserts that the operand does not match at the position.
+    NegativeLookAhead(Box<Ast>),
+    /// Positive lookbehind, e.g. `(?<=a)`, which asserts that the operand matches the chars just before the position.
+    /// The operand matches strings of a single length, which is the number of chars to look back.
+    LookBehind(Box<Ast>, usize),
+    /// Negative lookbehind, e.g. `(?<!a)`, which asserts that the operand does not match the chars just before the
+    /// position.
+    NegativeLookBehind(Box<Ast>, usize),
+}
+
+impl Clone for Ast {
+    fn clone(&self) -> Self {
+        match self {
+            Ast::Char(c) => Ast::Char(*c),
+            Ast::Concat(concat) => Ast::Concat(concat.clone()),
+            // The operands are cloned in a loop rather than by recursing into each `Or`, as in `or_operands`.
+            Ast::Or(..) => or_ast(self.or_operands().cloned().collect()).unwrap(),
+            Ast::Question(e) => Ast::Question(e.clone()),
+            Ast::Star(e) => Ast::Star(e.clone()),
+            Ast::Plus(e) => Ast::Plus(e.clone()),
+            Ast::Dot => Ast::Dot,
+            Ast::ScopedDot(newline) => Ast::ScopedDot(*newline),
+            Ast::Range(lo, hi) => Ast::Range(*lo, *hi),
+            Ast::SearchStart => Ast::SearchStart,
+            Ast::Empty => Ast::Empty,
+            Ast::LookAhead(e) => Ast::LookAhead(e.clone()),
+            Ast::NegativeLookAhead(e) => Ast::NegativeLookAhead(e.clone()),
+            Ast::LookBehind(e, width) => Ast::LookBehind(e.clone(), *width),
+            Ast::NegativeLookBehind(e, width) => Ast::NegativeLookBehind(e.clone(), *width),
+        }
+    }
+}
+
+impl Ast {
+    /// Convert into an AST that matches the reversed strings, e.g. `ab(cd|e)*` into `(dc|e)*ba`.
+    ///
+    /// A lookbehind becomes a lookahead of the reversed operand. A lookahead cannot be reversed into a lookahead, so it
+    /// is kept as it is and the result is only exact for an AST without lookaheads.
+    pub fn reverse(self) -> Self {
+        match self {
+            Ast::Concat(mut concat) => {
+                concat.reverse();
+                Ast::Concat(concat.into_iter().map(Ast::reverse).collect())
+            }
+            or @ Ast::Or(..) => {
+                let mut operands = or.into_or_operands().into_iter().map(Ast::reverse).rev();
+                let last = operands.next().unwrap();
+                operands.fold(last, |ast, operand| Ast::Or(operand.into(), ast.into()))
+            }
+            Ast::Question(e) => Ast::Question(e.reverse().into()),
+            Ast::Star(e) => Ast::Star(e.reverse().into()),
+            Ast::Plus(e) => Ast::Plus(e.reverse().into()),
+            Ast::LookBehind(e, _) => Ast::LookAhead(e.reverse().into()),
+            Ast::NegativeLookBehind(e, _) => Ast::NegativeLookAhead(e.reverse().into()),
+            Ast::Char(_)
+            | Ast::Dot
+            | Ast::ScopedDot(_)
+            | Ast::Range(..)
+            | Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_) => self,
+        }
+    }
+
+    /// Operands of the alternation chain `e1|e2|...|en` that the AST heads, or the AST itself if it is not an `Or`.
+    ///
+    /// The chain is nested to the right, and is walked in a loop rather than by recursing into each `Or`, so that a
+    /// long alternation does not overflow the stack.
+    pub(crate) fn or_operands(&self) -> impl Iterator<Item = &Ast> {
+        let mut next = Some(self);
+        iter::from_fn(move || match next? {
+            Ast::Or(lhs, rhs) => {
+                next = Some(rhs);
+                Some(&**lhs)
+            }
+            ast => next.take().map(|_| ast),
+        })
+    }
+
+    /// Operands of the alternation chain that the AST heads, taken by value, as in [`Ast::or_operands`].
+    pub(crate) fn into_or_operands(self) -> Vec<Ast> {
+        let mut operands = Vec::new();
+        let mut ast = self;
+        while let Ast::Or(lhs, rhs) = ast {
+            operands.push(*lhs);
+            ast = *rhs;
+        }
+        operands.push(ast);
+        operands
+    }
+
+    /// Visit the AST and all of its descendants in pre-order, i.e. every node before its operands, left to right.
+    ///
+    /// # Example
+    /// ```
+    /// use vmregex::{parse, Ast};
+    ///
+    /// let mut literals = Vec::new();
+    /// parse("a(b|c)*").unwrap().walk(&mut |ast| {
+    ///     if let Ast::Char(c) = ast {
+    ///         literals.push(*c);
+    ///     }
+    /// });
+    /// assert_eq!(literals, ['a', 'b', 'c']);
+    /// ```
+    pub fn walk(&self, f: &mut impl FnMut(&Ast)) {
+        // Use an explicit stack rather than recursion, so that a long alternation does not overflow the stack.
+        let mut stack = vec![self];
+        while let Some(ast) = stack.pop() {
+            f(ast);
+            match ast {
+                Ast::Concat(concat) => stack.extend(concat.iter().rev()),
+                Ast::Or(lhs, rhs) => {
+                    stack.push(rhs);
+                    stack.push(lhs);
+                }
+                Ast::Question(e)
+                | Ast::Star(e)
+                | Ast::Plus(e)
+                | Ast::LookAhead(e)
+                | Ast::NegativeLookAhead(e)
+                | Ast::LookBehind(e, _)
+                | Ast::NegativeLookBehind(e, _) => stack.push(e),
+                Ast::Char(_)
+                | Ast::Dot
+                | Ast::ScopedDot(_)
+                | Ast::Range(..)
+                | Ast::SearchStart
+                | Ast::Empty => {}
+            }
+        }
+    }
+
+    /// Key that is equal for equal ASTs, which writes the AST in prefix notation with a token for each node, e.g.
+    /// `&2,'a*'b` for `ab*`.
+    ///
+    /// The tokens are `'` followed by the char, `&n,` for a sequence of `n` elements, `|`, `?`, `*`, `+`, `.`, `s`
+    /// and `S` for `.` with the `s` flag set and cleared, `-` followed by the bounds of the range, `G` for `\G`, `e` for the empty string, `=` and `!` for the lookaheads,
+    /// and `<=n,` and `<!n,` for the lookbehinds of width `n`.
+    pub(crate) fn key(&self) -> String {
+        let mut key = String::new();
+        // `walk` visits every node before its operands, so a key is written with no recursion.
+        self.walk(&mut |ast| match ast {
+            Ast::Char(c) => {
+                key.push('\'');
+                key.push(*c);
+            }
+            Ast::Concat(concat) => key.push_str(&format!("&{},", concat.len())),
+            Ast::Or(..) => key.push('|'),
+            Ast::Question(_) => key.push('?'),
+            Ast::Star(_) => key.push('*'),
+            Ast::Plus(_) => key.push('+'),
+            Ast::Dot => key.push('.'),
+            Ast::ScopedDot(newline) => key.push(if *newline { 's' } else { 'S' }),
+            Ast::Range(lo, hi) => {
+                key.push('-');
+                key.push(*lo);
+                key.push(*hi);
+            }
+            Ast::SearchStart => key.push('G'),
+            Ast::Empty => key.push('e'),
+            Ast::LookAhead(_) => key.push('='),
+            Ast::NegativeLookAhead(_) => key.push('!'),
+            Ast::LookBehind(_, width) => key.push_str(&format!("<={width},")),
+            Ast::NegativeLookBehind(_, width) => key.push_str(&format!("<!{width},")),
+        });
+        key
+    }
+
+    /// Check if every match of the AST starts where the search began, i.e. every alternative begins with `\G`, e.g.
+    /// `\Ga|\Gb+`.
+    pub fn is_anchored_start(&self) -> bool {
+        match self {
+            Ast::SearchStart => true,
+            Ast::Concat(concat) => concat.first().is_some_and(Ast::is_anchored_start),
+            Ast::Or(..) => self.or_operands().all(Ast::is_anchored_start),
+            Ast::Plus(e) => e.is_anchored_start(),
+            Ast::Char(_)
+            | Ast::Dot
+            | Ast::ScopedDot(_)
+            | Ast::Range(..)
+            | Ast::Empty
+            | Ast::Question(_)
+            | Ast::Star(_)
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => false,
+        }
+    }
+
+    /// Minimum number of chars in a string matched by the AST, e.g. 2 for `ab*(c|de)`.
+    pub fn min_len(&self) -> usize {
+        match self {
+            Ast::Char(_) | Ast::Dot | Ast::ScopedDot(_) | Ast::Range(..) => 1,
+            Ast::Concat(concat) => concat.iter().map(Ast::min_len).sum(),
+            Ast::Or(..) => self
+                .or_operands()
+                .map(Ast::min_len)
+                .fold(usize::MAX, usize::min),
+            Ast::Question(_)
+            | Ast::Star(_)
+            | Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => 0,
+            Ast::Plus(e) => e.min_len(),
+        }
+    }
+
+    /// Maximum number of chars in a string matched by the AST, or `None` if it is unbounded, e.g. `Some(3)` for
+    /// `ab?(c|d)` and `None` for `ab*`.
+    pub fn max_len(&self) -> Option<usize> {
+        match self {
+            Ast::Char(_) | Ast::Dot | Ast::ScopedDot(_) | Ast::Range(..) => Some(1),
+            Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => Some(0),
+            Ast::Concat(concat) => concat
+                .iter()
+                .try_fold(0usize, |len, ast| len.checked_add(ast.max_len()?)),
+            Ast::Or(..) => self
+                .or_operands()
+                .try_fold(0, |len, operand| Some(len.max(operand.max_len()?))),
+            Ast::Question(e) => e.max_len(),
+            // Repeating an operand that only matches the empty string still only matches the empty string.
+            Ast::Star(e) | Ast::Plus(e) => e.max_len().filter(|&len| len == 0),
+        }
+    }
+
+    /// Chars that every string matched by the AST contains, sorted and without duplicates, e.g. `['a', 'c']` for
+    /// `ab?(c|cd)+`.
+    pub fn required_chars(&self) -> Vec<char> {
+        match self {
+            Ast::Char(c) => vec![*c],
+            Ast::Concat(concat) => {
+                let mut chars = concat
+                    .iter()
+                    .flat_map(Ast::required_chars)
+                    .collect::<Vec<_>>();
+                chars.sort_unstable();
+                chars.dedup();
+                chars
+            }
+            Ast::Or(lhs, rhs) => {
+                let mut chars = lhs.required_chars();
+                for operand in rhs.or_operands() {
+                    let operand = operand.required_chars();
+                    chars.retain(|c| operand.contains(c));
+                }
+                chars
+            }
+            Ast::Plus(e) => e.required_chars(),
+            // The operand of a lookaround is not part of the match.
+            Ast::Question(_)
+            | Ast::Star(_)
+            | Ast::Dot
+            | Ast::ScopedDot(_)
+            | Ast::Range(..)
+            | Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => Vec::new(),
+        }
+    }
+
+    /// Runs of literal chars that every string matched by the AST contains, in the order of the pattern and without
+    /// duplicates, e.g. `["foo", "bar"]` for `foo.*bar`.
+    ///
+    /// A literal is required by an alternation if every operand requires a literal containing it, e.g. `foo` for
+    /// `foo|xfoo`.
+    pub fn required_literals(&self) -> Vec<String> {
+        match self {
+            Ast::Char(c) => vec![String::from(*c)],
+            Ast::Concat(concat) => {
+                let mut literals = Vec::new();
+                let mut run = String::new();
+                for element in concat {
+                    if let Ast::Char(c) = element {
+                        run.push(*c);
+                        continue;
+                    }
+                    if !run.is_empty() {
+                        literals.push(mem::take(&mut run));
+                    }
+                    literals.extend(element.required_literals());
+                }
+                if !run.is_empty() {
+                    literals.push(run);
+                }
+
+                let mut unique = Vec::with_capacity(literals.len());
+                for literal in literals {
+                    if !unique.contains(&literal) {
+                        unique.push(literal);
+                    }
+                }
+                unique
+            }
+            Ast::Or(lhs, rhs) => {
+                let mut literals = lhs.required_literals();
+                for operand in rhs.or_operands() {
+                    let operand = operand.required_literals();
+                    literals.retain(|l| operand.iter().any(|o| o.contains(l.as_str())));
+                }
+                literals
+            }
+            Ast::Plus(e) => e.required_literals(),
+            // The operand of a lookaround is not part of the match.
+            Ast::Question(_)
+            | Ast::Star(_)
+            | Ast::Dot
+            | Ast::ScopedDot(_)
+            | Ast::Range(..)
+            | Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => Vec::new(),
+        }
+    }
+
+    /// Check if two operands of an alternation in the AST can both match at the same position, i.e. they can start
+    /// with the same char or one of them can match the empty string, e.g. `abc|ab` and `ab|[a-z]` but not `a|b`.
+    ///
+    /// The leftmost-first match then depends on the order of the operands. Operands that both match exactly one char
+    /// do not overlap, since either order matches the same, e.g. `a|[a-z]` or the ranges of `[a-cb]`.
+    pub fn has_overlapping_alternation(&self) -> bool {
+        // Use an explicit stack rather than recursion, as in `walk`.
+        let mut stack = vec![self];
+        while let Some(ast) = stack.pop() {
+            match ast {
+                Ast::Or(..) => {
+                    let operands = ast.or_operands().collect::<Vec<_>>();
+                    if operands_overlap(&operands) {
+                        return true;
+                    }
+                    stack.extend(operands);
+                }
+                Ast::Concat(concat) => stack.extend(concat),
+                Ast::Question(e)
+                | Ast::Star(e)
+                | Ast::Plus(e)
+                | Ast::LookAhead(e)
+                | Ast::NegativeLookAhead(e)
+                | Ast::LookBehind(e, _)
+                | Ast::NegativeLookBehind(e, _) => stack.push(e),
+                Ast::Char(_)
+                | Ast::Dot
+                | Ast::ScopedDot(_)
+                | Ast::Range(..)
+                | Ast::SearchStart
+                | Ast::Empty => {}
+            }
+        }
+        false
+    }
+
+    /// Check if the AST matches exactly one char, e.g. `a`, `[a-z_]` or `.`.
+    fn is_single_char(&self) -> bool {
+        match self {
+            Ast::Char(_) | Ast::Range(..) | Ast::Dot | Ast::ScopedDot(_) => true,
+            Ast::Or(..) => self.or_operands().all(Ast::is_single_char),
+            _ => false,
+        }
+    }
+
+    /// Add the inclusive ranges of the chars that a non-empty match of the AST can start with, and return whether it
+    /// can match the empty string.
+    fn first_chars(&self, ranges: &mut Vec<(char, char)>) -> bool {
+        match self {
+            Ast::Char(c) => {
+                ranges.push((*c, *c));
+                false
+            }
+            Ast::Range(lo, hi) => {
+                ranges.push((*lo, *hi));
+                false
+            }
+            Ast::Dot => {
+                ranges.push(('\0', char::MAX));
+                false
+            }
+            Ast::ScopedDot(newline) => {
+                if *newline {
+                    ranges.push(('\0', char::MAX));
+                } else {
+                    ranges.extend([('\0', '\u{9}'), ('\u{b}', char::MAX)]);
+                }
+                false
+            }
+            // The elements are tried in order while the previous ones can match the empty string.
+            Ast::Concat(concat) => concat.iter().all(|e| e.first_chars(ranges)),
+            Ast::Or(..) => {
+                // Every operand adds its ranges, even after a nullable one.
+                let mut nullable = false;
+                for operand in self.or_operands() {
+                    nullable |= operand.first_chars(ranges);
+                }
+                nullable
+            }
+            Ast::Question(e) | Ast::Star(e) => {
+                e.first_chars(ranges);
+                true
+            }
+            Ast::Plus(e) => e.first_chars(ranges),
+            // An assertion does not consume a char.
+            Ast::SearchStart
+            | Ast::Empty
+            | Ast::LookAhead(_)
+            | Ast::NegativeLookAhead(_)
+            | Ast::LookBehind(..)
+            | Ast::NegativeLookBehind(..) => true,
+        }
+    }
+}
+
+/// Check if two of the operands of an alternation can both match at the same position, for
+/// [`Ast::has_overlapping_alternation`].
+fn operands_overlap(operands: &[&Ast]) -> bool {
+    let mut ranges = Vec::new();
+    let mut first = Vec::new();
+    for (i, operand) in operands.iter().enumerate() {
+        if operand.first_chars(&mut first) {
+            return true;
+        }
+        // Operands that match exactly one char match the same whichever is tried first, so they count as one
+        // operand, whose ranges are not compared with each other.
+        let i = if operand.is_single_char() {
+            usize::MAX
+        } else {
+            i
+        };
+        ranges.extend(first.drain(..).map(|(lo, hi)| (lo, hi, i)));
+    }
+
+    // Sweep the ranges by their start, keeping the furthest end reached by an operand, and the furthest one reached
+    // by any other operand, so that a range is only compared with those of the other operands.
+    ranges.sort_unstable();
+    let mut furthest: Option<(char, usize)> = None;
+    let mut other: Option<char> = None;
+    for (lo, hi, i) in ranges {
+        let reach = match furthest {
+            Some((end, operand)) if operand != i => Some(end),
+            _ => other,
+        };
+        if reach.is_some_and(|end| end >= lo) {
+            return true;
+        }
+        match furthest {
+            Some((end, operand)) if operand == i => furthest = Some((end.max(hi), i)),
+            Some((end, _)) if hi > end => {
+                other = Some(end);
+                furthest = Some((hi, i));
+            }
+            Some(_) => other = other.max(Some(hi)),
+            None => furthest = Some((hi, i)),
+        }
+    }
+    false
 }
 
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum ParseError {
-    #[error("missing operand")]
     MissingOperand,
-    #[error("unclosed parenthesis")]
     UnclosedParenthesis,
-    #[error("unexpected parenthesis")]
     UnexpectedParenthesis,
-    #[error("invalid escape character {0}")]
     InvalidEscape(char),
-    #[error("empty expression")]
-    Empty,
+    VariableWidthLookbehind,
+    UnclosedBracket,
+    InvalidClassRange(char, char),
+    InvalidHexByte,
+    /// The minimum of a repetition such as `a{3,2}` is greater than its maximum, or a bound is above 1000.
+    InvalidRepetition,
+    /// Groups and quantifiers are nested deeper than `MAX_NESTING`.
+    NestingTooDeep,
+    /// A repetition copies its operand into more than `MAX_REPETITION_SIZE` nodes, e.g. `(a{1000}){1000}`.
+    RepetitionTooLarge,
+    /// An inline flag other than `s`, e.g. `m` in `(?sm)`.
+    UnsupportedFlag(char),
+    /// A bracket expression that starts with `^`, e.g. `[^a]`, since negated classes are not supported. An escaped
+    /// `^` is literal, e.g. `[\^a]`.
+    NegatedClass,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingOperand => write!(f, "missing operand"),
+            Self::UnclosedParenthesis => write!(f, "unclosed parenthesis"),
+            Self::UnexpectedParenthesis => write!(f, "unexpected parenthesis"),
+            Self::InvalidEscape(c) => write!(f, "invalid escape character {c}"),
+            Self::VariableWidthLookbehind => write!(f, "lookbehind of variable width"),
+            Self::UnclosedBracket => write!(f, "unclosed bracket"),
+            Self::InvalidClassRange(lo, hi) => write!(f, "invalid class range {lo}-{hi}"),
+            Self::InvalidHexByte => write!(f, "byte not written as two hex digits"),
+            Self::InvalidRepetition => write!(f, "invalid repetition bounds"),
+            Self::NestingTooDeep => write!(f, "pattern nested too deeply"),
+            Self::RepetitionTooLarge => write!(f, "repetition too large"),
+            Self::UnsupportedFlag(c) => write!(f, "unsupported flag {c}"),
+            Self::NegatedClass => write!(f, "negated class not supported"),
+        }
+    }
 }
 
+impl core::error::Error for ParseError {}
+
 /// Extract `concat` as an operand of the Or operator and append it to `concat_or`.
 fn append_concat(ctx: &mut Context) {
     if ctx.concat.len() == 1 {
@@ -56,33 +579,303 @@ fn or_ast(mut concat_or: Vec<Ast>) -> Option<Ast> {
     }
 }
 
+/// Parse a bracket expression after its `[`, e.g. `[a-z_]`, into the alternation of its chars and ranges.
+///
+/// `-` between two chars makes a range, and is literal elsewhere. `[`, `]`, `\`, `-` and `^` can be escaped. A
+/// leading `^` is rejected rather than read as a literal, since it would negate the class in other engines.
+fn class(chars: &mut impl Iterator<Item = char>, exact: bool) -> Result<Ast, ParseError> {
+    // Chars up to the closing `]`, and whether each was escaped.
+    let mut members = Vec::new();
+    loop {
+        match chars.next() {
+            Some(']') => break,
+            Some('\\') => match chars.next() {
+                Some(c @ ('[' | ']' | '\\' | '-' | '^')) => members.push((c, true)),
+                Some(c) => return Err(ParseError::InvalidEscape(c)),
+                None => return Err(ParseError::UnclosedBracket),
+            },
+            Some('^') if members.is_empty() => return Err(ParseError::NegatedClass),
+            Some(c) => members.push((c, false)),
+            None => return Err(ParseError::UnclosedBracket),
+        }
+    }
+
+    let mut operands = Vec::new();
+    let mut i = 0;
+    while let Some(&(lo, _)) = members.get(i) {
+        if let (Some(('-', false)), Some(&(hi, _))) = (members.get(i + 1), members.get(i + 2)) {
+            // Code points are compared, so that a range of chars outside the BMP works as any other.
+            if lo > hi {
+                return Err(ParseError::InvalidClassRange(lo, hi));
+            }
+            operands.push(if lo == hi && !exact {
+                Ast::Char(lo)
+            } else {
+                Ast::Range(lo, hi)
+            });
+            i += 3;
+        } else if exact {
+            operands.push(Ast::Range(lo, lo));
+            i += 1;
+        } else {
+            operands.push(Ast::Char(lo));
+            i += 1;
+        }
+    }
+
+    // An empty class such as `[]` has nothing to match.
+    or_ast(operands).ok_or(ParseError::MissingOperand)
+}
+
+/// Maximum bound of a repetition, since the operand is copied for each.
+pub const MAX_REPETITION: usize = 1000;
+
+/// Maximum number of AST nodes in the copies made by a repetition, since nested repetitions multiply, e.g. a million
+/// for `(a{1000}){1000}`.
+pub const MAX_REPETITION_SIZE: usize = 100_000;
+
+/// Parse the bounds of a repetition after its `{`, i.e. `n}`, `n,}` or `n,m}`, into the minimum and the maximum if
+/// any. Nothing is consumed and `None` is returned if the chars do not make a repetition, e.g. for `{b}` or `{,2}`.
+fn repetition(chars: &mut Peekable<Chars>) -> Result<Option<(usize, Option<usize>)>, ParseError> {
+    let mut ahead = chars.clone();
+    let number = |ahead: &mut Peekable<Chars>| {
+        let mut digits = String::new();
+        while let Some(c) = ahead.next_if(char::is_ascii_digit) {
+            digits.push(c);
+        }
+        // A number too large for `usize` is above `MAX_REPETITION` anyway.
+        (!digits.is_empty()).then(|| digits.parse().unwrap_or(usize::MAX))
+    };
+
+    let Some(min) = number(&mut ahead) else {
+        return Ok(None);
+    };
+    let max = if ahead.next_if_eq(&',').is_some() {
+        number(&mut ahead)
+    } else {
+        Some(min)
+    };
+    if ahead.next_if_eq(&'}').is_none() {
+        return Ok(None);
+    }
+    *chars = ahead;
+
+    if max.is_some_and(|max| min > max) || max.unwrap_or(min) > MAX_REPETITION {
+        return Err(ParseError::InvalidRepetition);
+    }
+    Ok(Some((min, max)))
+}
+
+/// Construct an AST that repeats the operand from `min` to `max` times, or unboundedly if there is no `max`, e.g.
+/// `aaa?` for `a{2,3}` and `aaa*` for `a{2,}`.
+fn repeat(ast: Ast, min: usize, max: Option<usize>) -> Result<Ast, ParseError> {
+    let mut size = 0usize;
+    ast.walk(&mut |_| size += 1);
+    if size.saturating_mul(max.unwrap_or(min + 1)) > MAX_REPETITION_SIZE {
+        return Err(ParseError::RepetitionTooLarge);
+    }
+
+    let mut concat = vec![ast.clone(); min];
+    match max {
+        Some(max) => concat.extend((min..max).map(|_| Ast::Question(ast.clone().into()))),
+        None => concat.push(Ast::Star(ast.into())),
+    }
+    Ok(match concat.len() {
+        0 => Ast::Empty,
+        1 => concat.pop().unwrap(),
+        _ => Ast::Concat(concat),
+    })
+}
+
+/// Translate a pattern over bytes written as pairs of hex digits, e.g. `89 50 (4e|4f)+`, into a pattern over the
+/// chars U+0000 to U+00FF that stand for the bytes.
+///
+/// Whitespace is skipped, and the operators, brackets, the openings of lookarounds such as `(?<=` and `\G` are kept.
+/// A byte that is a metacharacter is escaped in the output, so that it stands for itself.
+pub(crate) fn hex_pattern(pattern: &str) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(pattern.len());
+    let mut in_class = false;
+
+    let mut chars = pattern.chars().filter(|c| !c.is_whitespace()).peekable();
+    while let Some(c) = chars.next() {
+        if let Some(hi) = c.to_digit(16) {
+            let lo = chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or(ParseError::InvalidHexByte)?;
+            let byte = char::from((hi * 16 + lo) as u8);
+            match (in_class, byte) {
+                (false, '*' | '+' | '\\' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}') => {
+                    out.push('\\')
+                }
+                (true, '[' | ']' | '\\' | '-' | '^') => out.push('\\'),
+                // `.` cannot be escaped outside brackets.
+                (false, '.') => {
+                    out.push_str("[.]");
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(byte);
+            continue;
+        }
+
+        match (in_class, c) {
+            (false, '\\') => match chars.next() {
+                Some('G') => out.push_str("\\G"),
+                Some(c) => return Err(ParseError::InvalidEscape(c)),
+                None => return Err(ParseError::MissingOperand),
+            },
+            (false, '(') => {
+                out.push(c);
+                // `=`, `!` and `<` are only kept as the kind of a lookaround.
+                if chars.next_if_eq(&'?').is_some() {
+                    out.push('?');
+                    if chars.next_if_eq(&'<').is_some() {
+                        out.push('<');
+                    }
+                    if let Some(c) = chars.next_if(|&c| c == '=' || c == '!') {
+                        out.push(c);
+                    }
+                }
+            }
+            (false, '*' | '+' | '?' | ')' | '|' | '.') | (true, '-') => out.push(c),
+            (false, '[') | (true, ']') => {
+                in_class = !in_class;
+                out.push(c);
+            }
+            _ => return Err(ParseError::InvalidHexByte),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Parse the inline flags after a `(`, i.e. `?`, the flags to set, optionally `-` and the flags to clear, and `)` or
+/// `:`, e.g. `?s)` or `?-s:`. Return whether `.` matches `\n` after them, starting from `dot`, and whether they open a
+/// group, i.e. end with `:`. Nothing is consumed and `None` is returned if the chars are not inline flags, e.g. for
+/// `?=`.
+fn inline_flags(
+    chars: &mut Peekable<Chars>,
+    mut dot: Option<bool>,
+) -> Result<Option<(Option<bool>, bool)>, ParseError> {
+    let mut ahead = chars.clone();
+    if ahead.next_if_eq(&'?').is_none() {
+        return Ok(None);
+    }
+    let mut set = true;
+    let mut flags = 0;
+    loop {
+        match ahead.next() {
+            Some('-') if set => set = false,
+            Some('s') => {
+                dot = Some(set);
+                flags += 1;
+            }
+            Some(c) if c.is_ascii_alphabetic() => return Err(ParseError::UnsupportedFlag(c)),
+            Some(end @ (')' | ':')) if flags > 0 => {
+                *chars = ahead;
+                return Ok(Some((dot, end == ':')));
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Kind of group opened by a parenthesis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Plain,
+    LookAhead,
+    NegativeLookAhead,
+    LookBehind,
+    NegativeLookBehind,
+}
+
+/// Maximum depth of the AST, since the passes over it recurse into the subtrees and would overflow the stack. The
+/// depth is estimated while parsing, e.g. 3 levels for each `(a` of `(a(a(a)*)*)*`, and 2 for each `(?=` of
+/// `(?=(?=(?=a)))`, since the machine also recurses into a lookaround while matching.
+pub const MAX_NESTING: usize = 1000;
+
 #[derive(Debug, Default)]
 struct Context {
     concat: Vec<Ast>,
     concat_or: Vec<Ast>,
-    // Stack that holds the previous context `(concat, concat_or)`.
-    stack: Vec<(Vec<Ast>, Vec<Ast>)>,
+    // Depth of the last element of `concat`, and of the deepest element of the group.
+    depth: usize,
+    max_depth: usize,
+    // Whether `.` matches `\n` under an inline `s` flag, or `None` outside of one.
+    dot: Option<bool>,
+    // Stack that holds the previous context `(concat, concat_or, max_depth, dot)` and the kind of the group opened.
+    stack: Vec<Frame>,
+}
+
+/// Context saved by [`Context::stack`] when a group opens.
+type Frame = (Vec<Ast>, Vec<Ast>, usize, Option<bool>, Group);
+
+impl Context {
+    /// Push an element of the given depth to `concat`.
+    fn push(&mut self, ast: Ast, depth: usize) -> Result<(), ParseError> {
+        if depth > MAX_NESTING {
+            return Err(ParseError::NestingTooDeep);
+        }
+        self.concat.push(ast);
+        self.depth = depth;
+        self.max_depth = self.max_depth.max(depth);
+        Ok(())
+    }
 }
 
 /// Parse a regular expression pattern into an abstraction syntax tree (AST).
 pub fn parse(pattern: &str) -> Result<Ast, ParseError> {
+    parse_with_literals(pattern, &[], false)
+}
+
+/// Parse a pattern in which the metacharacters in `literals` stand for themselves, e.g. `|` within `a|b`.
+///
+/// With `exact_classes`, the single chars of a bracket expression are parsed as ranges of one char, e.g. `[a-cx]` as
+/// `Range('a', 'c')|Range('x', 'x')`, so that they are compared like the ranges rather than like the literals.
+pub(crate) fn parse_with_literals(
+    pattern: &str,
+    literals: &[char],
+    exact_classes: bool,
+) -> Result<Ast, ParseError> {
     let mut ctx = Context::default();
     let mut escaping = false;
+    // Inside `\Q...\E`, where every character but the closing `\E` is a literal.
+    let mut quoting = false;
 
     macro_rules! quantifier {
         ($operator:expr) => {
             if let Some(prev_ast) = ctx.concat.pop() {
-                ctx.concat.push($operator(Box::new(prev_ast)));
+                ctx.push($operator(Box::new(prev_ast)), ctx.depth + 1)?;
             } else {
                 return Err(ParseError::MissingOperand);
             }
         };
     }
 
-    for c in pattern.chars() {
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoting {
+            if c == '\\' && chars.next_if_eq(&'E').is_some() {
+                quoting = false;
+            } else {
+                ctx.push(Ast::Char(c), 1)?;
+            }
+            continue;
+        }
+
         if escaping {
-            if matches!(c, '*' | '+' | '\\' | '?' | '(' | ')' | '|') {
-                ctx.concat.push(Ast::Char(c));
+            if matches!(
+                c,
+                '*' | '+' | '\\' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}'
+            ) {
+                ctx.push(Ast::Char(c), 1)?;
+            } else if c == 'Q' {
+                quoting = true;
+            } else if c == 'G' {
+                ctx.push(Ast::SearchStart, 1)?;
             } else {
                 return Err(ParseError::InvalidEscape(c));
             }
@@ -90,6 +883,11 @@ pub fn parse(pattern: &str) -> Result<Ast, ParseError> {
             continue;
         }
 
+        if literals.contains(&c) {
+            ctx.push(Ast::Char(c), 1)?;
+            continue;
+        }
+
         match c {
             '|' => {
                 if ctx.concat.is_empty() {
@@ -102,34 +900,120 @@ pub fn parse(pattern: &str) -> Result<Ast, ParseError> {
             '?' => quantifier!(Ast::Question),
             '*' => quantifier!(Ast::Star),
             '+' => quantifier!(Ast::Plus),
-            '.' => ctx.concat.push(Ast::Dot),
+            '.' => ctx.push(ctx.dot.map_or(Ast::Dot, Ast::ScopedDot), 1)?,
+            '{' => match repetition(&mut chars)? {
+                Some((min, max)) => {
+                    let Some(prev_ast) = ctx.concat.pop() else {
+                        return Err(ParseError::MissingOperand);
+                    };
+                    // The copies are in a sequence, and those after the minimum under a quantifier.
+                    ctx.push(repeat(prev_ast, min, max)?, ctx.depth + 2)?;
+                }
+                // Not a repetition, so the `{` is literal.
+                None => ctx.push(Ast::Char('{'), 1)?,
+            },
+            // The chars and ranges of a class are operands of an alternation.
+            '[' => ctx.push(class(&mut chars, exact_classes)?, 2)?,
             '(' => {
+                // `?` after `(` opens a special group unless it is literal, e.g. `(?=a)` is then a plain group.
+                let special = !literals.contains(&'?');
+
+                // `(?#...)` is a comment, which matches nothing and is skipped up to the first `)`.
+                if special && chars.clone().take(2).eq(['?', '#']) {
+                    if !chars.any(|c| c == ')') {
+                        return Err(ParseError::UnclosedParenthesis);
+                    }
+                    continue;
+                }
+
+                // `(?s)` sets the flag for the rest of the group, and `(?s:` opens a group with it.
+                let dot = ctx.dot;
+                if special {
+                    if let Some((flag, scoped)) = inline_flags(&mut chars, dot)? {
+                        ctx.dot = flag;
+                        if !scoped {
+                            continue;
+                        }
+                    }
+                }
+
+                // `(?=` and `(?!` open a lookahead, and `(?<=` and `(?<!` a lookbehind.
+                let mut ahead = chars.clone();
+                let (group, len) = match (ahead.next(), ahead.next(), ahead.next()) {
+                    _ if !special => (Group::Plain, 0),
+                    (Some('?'), Some('='), _) => (Group::LookAhead, 2),
+                    (Some('?'), Some('!'), _) => (Group::NegativeLookAhead, 2),
+                    (Some('?'), Some('<'), Some('=')) => (Group::LookBehind, 3),
+                    (Some('?'), Some('<'), Some('!')) => (Group::NegativeLookBehind, 3),
+                    _ => (Group::Plain, 0),
+                };
+                if len > 0 {
+                    chars.nth(len - 1);
+                }
+
                 // Epilogue: push the current context.
-                let prev = (mem::take(&mut ctx.concat), mem::take(&mut ctx.concat_or));
+                let prev = (
+                    mem::take(&mut ctx.concat),
+                    mem::take(&mut ctx.concat_or),
+                    mem::take(&mut ctx.max_depth),
+                    dot,
+                    group,
+                );
                 ctx.stack.push(prev);
             }
             ')' => {
-                if let Some((mut prev_concat, prev_concat_or)) = ctx.stack.pop() {
-                    // Skip `()`.
-                    if ctx.concat.is_empty() {
-                        continue;
-                    }
+                if let Some((prev_concat, prev_concat_or, prev_max_depth, prev_dot, group)) =
+                    ctx.stack.pop()
+                {
+                    // A group of a single element is that element, and otherwise a sequence or an alternation of
+                    // sequences.
+                    let depth = if ctx.concat_or.is_empty() && ctx.concat.len() <= 1 {
+                        ctx.max_depth
+                    } else {
+                        ctx.max_depth + 2
+                    } + 2 * usize::from(group != Group::Plain);
 
-                    // Construct the AST of the expression in parentheses.
-                    append_concat(&mut ctx);
-                    if let Some(inner_ast) = or_ast(ctx.concat_or) {
-                        prev_concat.push(inner_ast);
-                    }
+                    // Construct the AST of the expression in parentheses. An empty group such as `()` matches the
+                    // empty string, so that a quantifier after it applies to it, e.g. `a()?b` is `ab`.
+                    let inner_ast = if ctx.concat.is_empty() {
+                        // The right operand of the Or operator is missing, e.g. `(a|)`.
+                        if !ctx.concat_or.is_empty() {
+                            return Err(ParseError::MissingOperand);
+                        }
+                        Ast::Empty
+                    } else {
+                        append_concat(&mut ctx);
+                        or_ast(mem::take(&mut ctx.concat_or)).unwrap_or(Ast::Empty)
+                    };
+                    let ast = match group {
+                        Group::Plain => inner_ast,
+                        Group::LookAhead => Ast::LookAhead(inner_ast.into()),
+                        Group::NegativeLookAhead => Ast::NegativeLookAhead(inner_ast.into()),
+                        Group::LookBehind | Group::NegativeLookBehind => {
+                            let width = inner_ast
+                                .max_len()
+                                .filter(|&len| len == inner_ast.min_len())
+                                .ok_or(ParseError::VariableWidthLookbehind)?;
+                            if group == Group::LookBehind {
+                                Ast::LookBehind(inner_ast.into(), width)
+                            } else {
+                                Ast::NegativeLookBehind(inner_ast.into(), width)
+                            }
+                        }
+                    };
 
                     // Prologue: Rewind the context.
                     ctx.concat = prev_concat;
                     ctx.concat_or = prev_concat_or;
+                    ctx.max_depth = prev_max_depth;
+                    ctx.dot = prev_dot;
+                    ctx.push(ast, depth)?;
                 } else {
                     return Err(ParseError::UnexpectedParenthesis);
                 }
             }
             '\\' => escaping = true,
-            _ => ctx.concat.push(Ast::Char(c)),
+            _ => ctx.push(Ast::Char(c), 1)?,
         }
     }
 
@@ -149,15 +1033,14 @@ pub fn parse(pattern: &str) -> Result<Ast, ParseError> {
         append_concat(&mut ctx);
     }
 
-    if let Some(ast) = or_ast(ctx.concat_or) {
-        Ok(ast)
-    } else {
-        Err(ParseError::Empty)
-    }
+    // An empty pattern matches the empty string.
+    Ok(or_ast(ctx.concat_or).unwrap_or(Ast::Empty))
 }
 
 #[cfg(test)]
 mod test {
+    use alloc::format;
+
     use super::*;
 
     #[test]
@@ -185,7 +1068,7 @@ mod test {
         assert_eq!(parse("|"), Err(ParseError::MissingOperand));
 
         // Empty expression
-        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse(""), Ok(Ast::Empty));
     }
 
     #[test]
@@ -200,6 +1083,34 @@ mod test {
         ]);
         assert_eq!(parse("ab(cd|ef)").unwrap(), ast);
 
+        // The operands of an alternation belong to the group they are in.
+        let or = |lhs: Ast, rhs: Ast| Ast::Or(lhs.into(), rhs.into());
+        let (a, b, c, d, e) = (
+            Ast::Char('a'),
+            Ast::Char('b'),
+            Ast::Char('c'),
+            Ast::Char('d'),
+            Ast::Char('e'),
+        );
+        let ast = Ast::Concat(vec![or(a.clone(), b.clone()), or(c.clone(), d.clone())]);
+        assert_eq!(parse("(a|b)(c|d)").unwrap(), ast);
+        let ast = or(or(a.clone(), b.clone()), or(c.clone(), d.clone()));
+        assert_eq!(parse("(a|b)|(c|d)").unwrap(), ast);
+        let ast = or(or(a.clone(), b.clone()), c.clone());
+        assert_eq!(parse("((a|b)|c)").unwrap(), ast);
+        let ast = Ast::Concat(vec![
+            a.clone(),
+            or(b.clone(), or(c.clone(), d.clone())),
+            e.clone(),
+        ]);
+        assert_eq!(parse("a(b|(c|d))e").unwrap(), ast);
+        let ast = or(
+            Ast::Concat(vec![a.clone(), or(b.clone(), c.clone())]),
+            Ast::Concat(vec![d, or(e, a)]),
+        );
+        assert_eq!(parse("a(b|c)|d(e|a)").unwrap(), ast);
+        assert_eq!(parse("(a|b))|(c"), Err(ParseError::UnexpectedParenthesis));
+
         // Error
         assert_eq!(parse("(ab"), Err(ParseError::UnclosedParenthesis));
         assert_eq!(parse("ab)"), Err(ParseError::UnexpectedParenthesis));
@@ -207,7 +1118,85 @@ mod test {
         assert_eq!(parse(")"), Err(ParseError::UnexpectedParenthesis));
 
         // Empty expression
-        assert_eq!(parse("()"), Err(ParseError::Empty));
+        assert_eq!(parse("()"), Ok(Ast::Empty));
+        assert_eq!(
+            parse("x()y").unwrap(),
+            Ast::Concat(vec![Ast::Char('x'), Ast::Empty, Ast::Char('y')])
+        );
+        assert_eq!(parse("(a|)"), Err(ParseError::MissingOperand));
+    }
+
+    #[test]
+    fn quantified_empty_group() {
+        let ast = Ast::Concat(vec![
+            Ast::Char('a'),
+            Ast::Question(Ast::Empty.into()),
+            Ast::Char('b'),
+        ]);
+        assert_eq!(parse("a()?b").unwrap(), ast);
+        assert_eq!(parse("()*"), Ok(Ast::Star(Ast::Empty.into())));
+        assert_eq!(parse("(())+"), Ok(Ast::Plus(Ast::Empty.into())));
+    }
+
+    #[test]
+    fn repetition() {
+        let a = || Ast::Char('a');
+        assert_eq!(parse("a{2}").unwrap(), Ast::Concat(vec![a(), a()]));
+        assert_eq!(
+            parse("a{1,3}").unwrap(),
+            Ast::Concat(vec![
+                a(),
+                Ast::Question(a().into()),
+                Ast::Question(a().into())
+            ])
+        );
+        assert_eq!(
+            parse("a{2,}").unwrap(),
+            Ast::Concat(vec![a(), a(), Ast::Star(a().into())])
+        );
+        assert_eq!(parse("a{1}").unwrap(), a());
+        assert_eq!(parse("a{0}").unwrap(), Ast::Empty);
+        assert_eq!(parse("a{0,}").unwrap(), Ast::Star(a().into()));
+        assert_eq!(
+            parse("(ab){2}").unwrap(),
+            Ast::Concat(vec![
+                Ast::Concat(vec![a(), Ast::Char('b')]),
+                Ast::Concat(vec![a(), Ast::Char('b')]),
+            ])
+        );
+
+        // A `{` that does not start a repetition is literal.
+        let literal = |s: &str| Ast::Concat(s.chars().map(Ast::Char).collect());
+        assert_eq!(parse("a{b}").unwrap(), literal("a{b}"));
+        assert_eq!(parse("a{").unwrap(), literal("a{"));
+        assert_eq!(parse("a{,2}").unwrap(), literal("a{,2}"));
+        assert_eq!(parse("a{2").unwrap(), literal("a{2"));
+        assert_eq!(parse("a{2,b}").unwrap(), literal("a{2,b}"));
+        assert_eq!(parse("{}").unwrap(), literal("{}"));
+        assert_eq!(parse(r"a\{2}").unwrap(), literal("a{2}"));
+
+        assert_eq!(parse("{2}"), Err(ParseError::MissingOperand));
+        assert_eq!(parse("a{3,2}"), Err(ParseError::InvalidRepetition));
+        assert_eq!(parse("a{1001}"), Err(ParseError::InvalidRepetition));
+        assert_eq!(
+            parse("a{99999999999999999999999}"),
+            Err(ParseError::InvalidRepetition)
+        );
+
+        // Nested repetitions multiply the copies.
+        assert!(parse("(a{50}){1000}").is_ok());
+        assert_eq!(
+            parse("(a{1000}){1000}"),
+            Err(ParseError::RepetitionTooLarge)
+        );
+        assert_eq!(
+            parse("((a{1000}){1000}){1000}"),
+            Err(ParseError::RepetitionTooLarge)
+        );
+        assert_eq!(
+            parse("((ab){100}){1000,}"),
+            Err(ParseError::RepetitionTooLarge)
+        );
     }
 
     #[test]
@@ -226,6 +1215,33 @@ mod test {
         assert_eq!(parse(r"a\bc"), Err(ParseError::InvalidEscape('b')));
     }
 
+    #[test]
+    fn quote() {
+        let ast = Ast::Concat(vec![
+            Ast::Char('a'),
+            Ast::Char('.'),
+            Ast::Char('b'),
+            Ast::Char('*'),
+        ]);
+        assert_eq!(parse(r"\Qa.b*\E").unwrap(), ast);
+
+        let ast = Ast::Concat(vec![Ast::Plus(Ast::Char('(').into()), Ast::Char('x')]);
+        assert_eq!(parse(r"\Q(\E+x").unwrap(), ast);
+
+        // A backslash not followed by `E` is a literal.
+        let ast = Ast::Concat(vec![Ast::Char('\\'), Ast::Char('|')]);
+        assert_eq!(parse(r"\Q\|\E").unwrap(), ast);
+        let ast = Ast::Char('\\');
+        assert_eq!(parse(r"\Q\\E").unwrap(), ast);
+
+        // Unterminated quoting runs until the end of the pattern.
+        let ast = Ast::Concat(vec![Ast::Char('a'), Ast::Char(')'), Ast::Char('\\')]);
+        assert_eq!(parse(r"\Qa)\").unwrap(), ast);
+
+        // Empty expression
+        assert_eq!(parse(r"\Q\E"), Ok(Ast::Empty));
+    }
+
     #[test]
     fn question() {
         let ast = Ast::Question(Ast::Char('a').into());
@@ -260,4 +1276,435 @@ mod test {
         let ast = Ast::Concat(vec![Ast::Char('a'), Ast::Dot, Ast::Char('b')]);
         assert_eq!(parse("a.b").unwrap(), ast);
     }
+
+    #[test]
+    fn reverse() {
+        let ast = Ast::Concat(vec![
+            Ast::Char('b'),
+            Ast::Char('a'),
+            Ast::Star(
+                Ast::Or(
+                    Ast::Concat(vec![Ast::Char('c'), Ast::Char('d')]).into(),
+                    Ast::Char('e').into(),
+                )
+                .into(),
+            ),
+        ]);
+        assert_eq!(parse("(dc|e)*ab").unwrap().reverse(), ast);
+
+        let ast = Ast::Concat(vec![Ast::Dot, Ast::Plus(Ast::Char('x').into())]);
+        assert_eq!(parse("x+.").unwrap().reverse(), ast);
+    }
+
+    #[test]
+    fn group_quantifier() {
+        let ast = Ast::Plus(Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into());
+        assert_eq!(parse("(ab)+").unwrap(), ast);
+
+        let ast = Ast::Concat(vec![Ast::Char('a'), Ast::Plus(Ast::Char('b').into())]);
+        assert_eq!(parse("ab+").unwrap(), ast);
+
+        // The quantifier applies to the whole group, not to its last element.
+        let ast = Ast::Concat(vec![
+            Ast::Star(Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into()),
+            Ast::Char('c'),
+        ]);
+        assert_eq!(parse("(ab)*c").unwrap(), ast);
+
+        let ast = Ast::Plus(Ast::Or(Ast::Char('a').into(), Ast::Char('b').into()).into());
+        assert_eq!(parse("(a|b)+").unwrap(), ast);
+
+        let ast = Ast::Question(
+            Ast::Concat(vec![
+                Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]),
+                Ast::Char('c'),
+            ])
+            .into(),
+        );
+        assert_eq!(parse("((ab)c)?").unwrap(), ast);
+
+        let ast = Ast::Concat(vec![
+            Ast::Char('x'),
+            Ast::Plus(Ast::Star(Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into()).into()),
+        ]);
+        assert_eq!(parse("x((ab)*)+").unwrap(), ast);
+    }
+
+    #[test]
+    fn min_len() {
+        assert_eq!(parse("abcdef").unwrap().min_len(), 6);
+        assert_eq!(parse("ab*(c|de)").unwrap().min_len(), 2);
+        assert_eq!(parse("(ab)+c?.").unwrap().min_len(), 3);
+        assert_eq!(parse("abc|d|ef").unwrap().min_len(), 1);
+        assert_eq!(parse("(abc)*").unwrap().min_len(), 0);
+    }
+
+    #[test]
+    fn walk() {
+        let mut count = 0;
+        parse("a(b|c)*").unwrap().walk(&mut |_| count += 1);
+        // Concat, a, Star, Or, b, c
+        assert_eq!(count, 6);
+
+        let mut nodes = Vec::new();
+        parse("a+|.")
+            .unwrap()
+            .walk(&mut |ast| nodes.push(ast.clone()));
+        assert_eq!(
+            nodes,
+            [
+                Ast::Or(Ast::Plus(Ast::Char('a').into()).into(), Ast::Dot.into()),
+                Ast::Plus(Ast::Char('a').into()),
+                Ast::Char('a'),
+                Ast::Dot,
+            ]
+        );
+    }
+
+    #[test]
+    fn search_start() {
+        let ast = Ast::Concat(vec![Ast::SearchStart, Ast::Char('a'), Ast::Char('b')]);
+        assert_eq!(parse("\\Gab").unwrap(), ast);
+
+        let ast = Ast::Or(
+            Ast::Char('a').into(),
+            Ast::Concat(vec![Ast::SearchStart, Ast::Char('b')]).into(),
+        );
+        assert_eq!(parse("a|\\Gb").unwrap(), ast);
+        assert_eq!(parse("\\G").unwrap().min_len(), 0);
+
+        // `^` and `$` are not anchors, but literal chars.
+        let ast = Ast::Concat(vec![Ast::Char('^'), Ast::Char('a'), Ast::Char('$')]);
+        assert_eq!(parse("^a$").unwrap(), ast);
+    }
+
+    #[test]
+    fn max_len() {
+        assert_eq!(parse("abc").unwrap().max_len(), Some(3));
+        assert_eq!(parse("ab?(c|de)").unwrap().max_len(), Some(4));
+        assert_eq!(parse("abc|d|ef").unwrap().max_len(), Some(3));
+        assert_eq!(parse("ab*").unwrap().max_len(), None);
+        assert_eq!(parse("(a|b+)").unwrap().max_len(), None);
+        assert_eq!(parse("(\\G)+a").unwrap().max_len(), Some(1));
+    }
+
+    #[test]
+    fn required_chars() {
+        let required = |pattern| parse(pattern).unwrap().required_chars();
+        assert_eq!(required("cab"), ['a', 'b', 'c']);
+        assert_eq!(required("ab?(c|cd)+"), ['a', 'c']);
+        assert_eq!(required("abab"), ['a', 'b']);
+        assert_eq!(required("(ab|ba|ca)"), ['a']);
+        assert_eq!(required("a|b"), []);
+        assert_eq!(required("a*b?.[x-z]"), []);
+        assert_eq!(required("(?=a)b(?<!c)"), ['b']);
+        assert_eq!(required(""), []);
+    }
+
+    #[test]
+    fn nesting() {
+        let stars = |n| format!("a{}", "*".repeat(n));
+        assert!(parse(&stars(MAX_NESTING - 1)).is_ok());
+        assert_eq!(parse(&stars(MAX_NESTING)), Err(ParseError::NestingTooDeep));
+        // A group of a single element is that element, so it adds no level.
+        assert!(parse(&format!(
+            "{}a{}",
+            "(".repeat(MAX_NESTING),
+            ")".repeat(MAX_NESTING)
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn required_literals() {
+        let required = |pattern| parse(pattern).unwrap().required_literals();
+        assert_eq!(required("foo.*bar"), ["foo", "bar"]);
+        assert_eq!(required("(foo|bar)baz"), ["baz"]);
+        assert_eq!(required("ab?c"), ["a", "c"]);
+        assert_eq!(required("x(abc)+y"), ["x", "abc", "y"]);
+        assert_eq!(required("ab.ab"), ["ab"]);
+        assert_eq!(required("(foo|xfoo|foobar)"), ["foo"]);
+        assert_eq!(required("(foo|bar)"), Vec::<String>::new());
+        assert_eq!(required("(?=foo)bar"), ["bar"]);
+        assert_eq!(required("a*"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn overlapping_alternation() {
+        let overlapping = |pattern| parse(pattern).unwrap().has_overlapping_alternation();
+        assert!(overlapping("(abc|ab)"));
+        assert!(overlapping("x(ab|[a-z])"));
+        assert!(overlapping("(a|b|.c)"));
+        assert!(overlapping("(b|c|a|xb|b?d)"));
+        assert!(overlapping("(a*|b)"));
+        assert!(overlapping("((?=a)b|b)"));
+        assert!(overlapping("a+(x|(y|z)|zz)"));
+        assert!(!overlapping("(a|b)"));
+        // Operands of a single char match the same in either order, as in a class.
+        assert!(!overlapping("[a-cb]"));
+        assert!(!overlapping("x[a-c0-9b]y"));
+        assert!(!overlapping("[f-fe-f]|[a-a]"));
+        assert!(!overlapping("(a|[a-z]|.)"));
+        assert!(!overlapping("(ab|b|cd|d|[e-g]|[h-z])"));
+        assert!(!overlapping("(a+|b*c|\\Gd)"));
+        assert!(!overlapping("abc"));
+    }
+
+    #[test]
+    fn literals() {
+        let ast = Ast::Concat(vec![Ast::Char('a'), Ast::Char('|'), Ast::Char('b')]);
+        assert_eq!(parse_with_literals("a|b", &['|'], false).unwrap(), ast);
+
+        let ast = Ast::Concat(vec![
+            Ast::Char('('),
+            Ast::Plus(Ast::Char('a').into()),
+            Ast::Char(')'),
+        ]);
+        assert_eq!(
+            parse_with_literals("(a+)", &['(', ')'], false).unwrap(),
+            ast
+        );
+
+        // An escape still works for the other metacharacters.
+        let ast = Ast::Concat(vec![Ast::Char('.'), Ast::Char('*')]);
+        assert_eq!(parse_with_literals(r".\*", &['.'], false).unwrap(), ast);
+        let ast = Ast::Concat(vec![Ast::Char('\\'), Ast::Char('a')]);
+        assert_eq!(parse_with_literals(r"\a", &['\\'], false).unwrap(), ast);
+
+        // A literal `?` after `(` does not open a lookaround, a comment or a flag group.
+        let group = |c| Ast::Concat(vec![Ast::Char('?'), Ast::Char(c), Ast::Char('a')]);
+        for (pattern, c) in [
+            ("(?=a)", '='),
+            ("(?!a)", '!'),
+            ("(?#a)", '#'),
+            ("(?:a)", ':'),
+        ] {
+            assert_eq!(
+                parse_with_literals(pattern, &['?'], false),
+                Ok(group(c)),
+                "{pattern}"
+            );
+        }
+        let ast = Ast::Concat(vec![
+            Ast::Char('?'),
+            Ast::Char('<'),
+            Ast::Char('='),
+            Ast::Char('a'),
+        ]);
+        assert_eq!(parse_with_literals("(?<=a)", &['?'], false), Ok(ast));
+        let ast = Ast::Concat(vec![
+            Ast::Concat(vec![Ast::Char('?'), Ast::Char('s')]),
+            Ast::Dot,
+        ]);
+        assert_eq!(parse_with_literals("(?s).", &['?'], false), Ok(ast));
+    }
+
+    #[test]
+    fn look_ahead() {
+        let ast = Ast::Concat(vec![
+            Ast::LookAhead(Ast::Concat(vec![Ast::Star(Ast::Dot.into()), Ast::Char('1')]).into()),
+            Ast::Plus(Ast::Dot.into()),
+        ]);
+        assert_eq!(parse("(?=.*1).+").unwrap(), ast);
+
+        let ast = Ast::Concat(vec![
+            Ast::Char('a'),
+            Ast::NegativeLookAhead(Ast::Or(Ast::Char('b').into(), Ast::Char('c').into()).into()),
+        ]);
+        assert_eq!(parse("a(?!b|c)").unwrap(), ast);
+        assert_eq!(parse("(?=)").unwrap(), Ast::LookAhead(Ast::Empty.into()));
+        assert_eq!(
+            parse("(?=a)+").unwrap(),
+            Ast::Plus(Ast::LookAhead(Ast::Char('a').into()).into())
+        );
+
+        // Error
+        assert_eq!(parse("(?=a"), Err(ParseError::UnclosedParenthesis));
+        assert_eq!(parse("(?!a|)"), Err(ParseError::MissingOperand));
+        assert_eq!(parse("(?a)"), Err(ParseError::UnsupportedFlag('a')));
+        assert_eq!(parse("(?%)"), Err(ParseError::MissingOperand));
+    }
+
+    #[test]
+    fn look_behind() {
+        let ast = Ast::Concat(vec![
+            Ast::LookBehind(Ast::Char('$').into(), 1),
+            Ast::Plus(Ast::Char('1').into()),
+        ]);
+        assert_eq!(parse("(?<=$)1+").unwrap(), ast);
+
+        let ast = Ast::NegativeLookBehind(
+            Ast::Or(
+                Ast::Concat(vec![Ast::Char('a'), Ast::Char('b')]).into(),
+                Ast::Concat(vec![Ast::Dot, Ast::Char('c')]).into(),
+            )
+            .into(),
+            2,
+        );
+        assert_eq!(parse("(?<!ab|.c)").unwrap(), ast);
+        assert_eq!(
+            parse("(?<=)").unwrap(),
+            Ast::LookBehind(Ast::Empty.into(), 0)
+        );
+
+        // Error
+        assert_eq!(parse("(?<=a+)"), Err(ParseError::VariableWidthLookbehind));
+        assert_eq!(parse("(?<!a|bc)"), Err(ParseError::VariableWidthLookbehind));
+        assert_eq!(parse("(?<=a"), Err(ParseError::UnclosedParenthesis));
+        assert_eq!(parse("(?<a)"), Err(ParseError::MissingOperand));
+    }
+
+    #[test]
+    fn hex_pattern() {
+        use super::hex_pattern;
+
+        assert_eq!(hex_pattern("41 42+ (4a|4B)?").unwrap(), "AB+(J|K)?");
+        assert_eq!(hex_pattern("\\G00 ff.").unwrap(), "\\G\0ÿ.");
+        // Bytes that are metacharacters are escaped.
+        assert_eq!(hex_pattern("2a 2e 5c").unwrap(), "\\*[.]\\\\");
+        assert_eq!(hex_pattern("41 7b 32 7d").unwrap(), "A\\{2\\}");
+        assert_eq!(hex_pattern("[00-1f 2d 5d]").unwrap(), "[\0-\u{1f}\\-\\]]");
+        assert_eq!(hex_pattern("[5e 41]").unwrap(), "[\\^A]");
+        assert_eq!(hex_pattern("(?=41)(?<!42)").unwrap(), "(?=A)(?<!B)");
+        assert_eq!(hex_pattern("( ? < = 41)(?!42)").unwrap(), "(?<=A)(?!B)");
+
+        assert_eq!(hex_pattern("4"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("4 g"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("41 x"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("41-42"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("41=42"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("41!42"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("(<41)"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("(?41=)"), Err(ParseError::InvalidHexByte));
+        assert_eq!(hex_pattern("\\Q41"), Err(ParseError::InvalidEscape('Q')));
+    }
+
+    #[test]
+    fn class() {
+        let ast = Ast::Plus(
+            Ast::Or(
+                Ast::Range('a', 'z').into(),
+                Ast::Or(Ast::Char('_').into(), Ast::Range('0', '9').into()).into(),
+            )
+            .into(),
+        );
+        assert_eq!(parse("[a-z_0-9]+").unwrap(), ast);
+        assert_eq!(parse("[😀-😏]").unwrap(), Ast::Range('😀', '😏'));
+        assert_eq!(parse("[x-x]").unwrap(), Ast::Char('x'));
+        // `-` at either end is literal, and so is an escaped one.
+        let ast = Ast::Or(
+            Ast::Char('-').into(),
+            Ast::Or(Ast::Char('a').into(), Ast::Char('-').into()).into(),
+        );
+        assert_eq!(parse("[-a-]").unwrap(), ast);
+        let ast = Ast::Or(
+            Ast::Char('a').into(),
+            Ast::Or(Ast::Char('-').into(), Ast::Char('z').into()).into(),
+        );
+        assert_eq!(parse(r"[a\-z]").unwrap(), ast);
+        assert_eq!(
+            parse(r"[\]\[]").unwrap(),
+            Ast::Or(Ast::Char(']').into(), Ast::Char('[').into())
+        );
+        // Metacharacters are literal inside a class.
+        assert_eq!(
+            parse("[.*]").unwrap(),
+            Ast::Or(Ast::Char('.').into(), Ast::Char('*').into())
+        );
+        assert_eq!(
+            parse(r"\[a]").unwrap(),
+            Ast::Concat(vec![Ast::Char('['), Ast::Char('a'), Ast::Char(']')])
+        );
+
+        // Error
+        assert_eq!(parse("[z-a]"), Err(ParseError::InvalidClassRange('z', 'a')));
+        assert_eq!(
+            parse("[😏-😀]"),
+            Err(ParseError::InvalidClassRange('😏', '😀'))
+        );
+        assert_eq!(parse("[a-z"), Err(ParseError::UnclosedBracket));
+        assert_eq!(parse(r"[a\"), Err(ParseError::UnclosedBracket));
+        assert_eq!(parse(r"[\d]"), Err(ParseError::InvalidEscape('d')));
+        assert_eq!(parse("[]"), Err(ParseError::MissingOperand));
+
+        // A leading `^` would negate the class elsewhere, so it is rejected unless escaped.
+        assert_eq!(parse("[^a]"), Err(ParseError::NegatedClass));
+        assert_eq!(
+            parse(r"[\^a]").unwrap(),
+            Ast::Or(Ast::Char('^').into(), Ast::Char('a').into())
+        );
+        assert_eq!(
+            parse("[a^]").unwrap(),
+            Ast::Or(Ast::Char('a').into(), Ast::Char('^').into())
+        );
+    }
+
+    #[test]
+    fn comment() {
+        assert_eq!(parse("a(?#x)b"), parse("ab"));
+        assert_eq!(parse("a(?#(|*)b"), parse("ab"));
+        assert_eq!(parse("(?#only a comment)"), Ok(Ast::Empty));
+        // A quantifier after a comment applies to what precedes the comment.
+        assert_eq!(parse("a(?#x)+"), parse("a+"));
+
+        // Error
+        assert_eq!(parse("a(?#x"), Err(ParseError::UnclosedParenthesis));
+    }
+
+    #[test]
+    fn inline_flags() {
+        let dot = Ast::ScopedDot(true);
+        let no_newline = Ast::ScopedDot(false);
+        assert_eq!(parse("(?s)."), Ok(dot.clone()));
+        assert_eq!(parse("(?-s)."), Ok(no_newline.clone()));
+        assert_eq!(parse("(?s-s)."), Ok(no_newline.clone()));
+        assert_eq!(parse("(?s)"), Ok(Ast::Empty));
+        // A scoped group restores the flag of the group around it when it closes.
+        assert_eq!(
+            parse("(?s:.)."),
+            Ok(Ast::Concat(vec![dot.clone(), Ast::Dot]))
+        );
+        assert_eq!(
+            parse("(?s:a(?-s:.).)."),
+            Ok(Ast::Concat(vec![
+                Ast::Concat(vec![Ast::Char('a'), no_newline.clone(), dot.clone()]),
+                Ast::Dot,
+            ]))
+        );
+        // A flag set without a scope lasts up to the end of the group, across alternatives.
+        assert_eq!(
+            parse("(a(?s).|.)."),
+            Ok(Ast::Concat(vec![
+                Ast::Or(
+                    Ast::Concat(vec![Ast::Char('a'), dot.clone()]).into(),
+                    dot.clone().into()
+                ),
+                Ast::Dot,
+            ]))
+        );
+        assert_eq!(
+            parse("(?=(?-s).)."),
+            Ok(Ast::Concat(vec![
+                Ast::LookAhead(no_newline.into()),
+                Ast::Dot
+            ]))
+        );
+
+        // Only the `s` flag is supported.
+        for (pattern, flag) in [
+            ("(?sm)a", 'm'),
+            ("(?sm:.^)", 'm'),
+            ("(?-m:.)", 'm'),
+            ("a(?i)", 'i'),
+        ] {
+            assert_eq!(
+                parse(pattern),
+                Err(ParseError::UnsupportedFlag(flag)),
+                "{pattern}"
+            );
+        }
+        for pattern in ["(?)a", "(?-)a", "(?s"] {
+            assert_eq!(parse(pattern), Err(ParseError::MissingOperand), "{pattern}");
+        }
+    }
 }
@@ -0,0 +1,52 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use vmregex::RegexBuilder;
+
+/// Allocator that counts the allocated bytes, to check how compilation scales with the pattern.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Number of bytes allocated to compile the pattern with the optimizations.
+fn compile_allocated(pattern: &str) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    RegexBuilder::new(pattern).optimize(true).build().unwrap();
+    ALLOCATED.load(Ordering::Relaxed) - before
+}
+
+// The passes move the subtrees of the AST rather than cloning them, so compiling a pattern 4 times as large should
+// allocate about 4 times as much, and far less than the 16 times of a quadratic pass.
+#[test]
+fn compile_scales_linearly() {
+    let words = |n: usize| {
+        (0..n)
+            .map(|i| format!("ab{i}c"))
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+    // The passes recurse into the groups, so the nesting is kept shallow enough for the stack.
+    let nested = |n: usize| format!("{}a{}", "(b|".repeat(n), ")*".repeat(n));
+
+    for (small, large) in [(words(500), words(2000)), (nested(50), nested(200))] {
+        let small = compile_allocated(&small);
+        let large = compile_allocated(&large);
+        assert!(large < small * 6, "{small} bytes, then {large} bytes");
+    }
+}
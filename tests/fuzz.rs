@@ -0,0 +1,132 @@
+use vmregex::{parse, ParseError, Regex, RegexBuilder, SyntaxError};
+
+/// Metacharacters, escapes and literals that random patterns are made of.
+const ALPHABET: &[&str] = &[
+    "a", "b", "é", "0", "9", "(", ")", "|", "*", "+", "?", ".", "[", "]", "^", "-", "{", "}", ",",
+    "\\", "\\G", "\\Q", "\\E", "(?=", "(?!", "(?<=", "(?<!", "(?#", "#", "=", "!", "<", " ", "(?s",
+    "(?-s", "s", ":",
+];
+
+/// Small patterns that parse on their own, which the long alternations and the repetitions are made of.
+const ATOMS: &[&str] = &[
+    "a", "b", "é", ".", "[a-b]", "\\G", "(?=a)", "(?<!b)", "a*", "b?", "(a|b)+", "()", "(?s:.)",
+];
+
+/// Xorshift generator, so that every run tries the same patterns and a failure can be reproduced.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Compile the pattern with every combination of options, and match the compiled ones against a few texts.
+///
+/// Any outcome is fine as long as nothing panics: the pattern either compiles or returns a typed error.
+fn compile_and_match(pattern: &str) {
+    let _ = parse(pattern);
+    for optimize in [false, true] {
+        for dot_matches_newline in [false, true] {
+            let Ok(re) = RegexBuilder::new(pattern)
+                .optimize(optimize)
+                .dot_matches_newline(dot_matches_newline)
+                // The suffix is looked for backward in half of the builds, and from every offset in the others.
+                .reverse_program(optimize)
+                .build()
+            else {
+                continue;
+            };
+            // Neither code generation nor the removal of jumps leaves a dead instruction behind.
+            assert_eq!(re.unreachable_instructions(), [], "{pattern}");
+            for text in ["", "a", "ab", "ba\n", "é0b", "aab9"] {
+                let _ = re.is_match(text);
+                let _ = re.is_full_match(text);
+                let _ = re.is_suffix_match(text);
+                let _ = re.longest_match(text);
+                let _ = re.find_iter(text).count();
+            }
+        }
+    }
+}
+
+/// Number of random patterns tried, which can be raised to search longer, e.g. with `cargo test --release`.
+const PATTERNS: usize = 2_000;
+
+#[test]
+fn random_patterns() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..PATTERNS {
+        let len = rng.below(10);
+        let pattern = (0..len)
+            .map(|_| ALPHABET[rng.below(ALPHABET.len())])
+            .collect::<String>();
+        compile_and_match(&pattern);
+    }
+}
+
+/// Sequence of a few `ATOMS`.
+fn random_atoms(rng: &mut Rng) -> String {
+    (0..1 + rng.below(3))
+        .map(|_| ATOMS[rng.below(ATOMS.len())])
+        .collect()
+}
+
+// A long flat alternation used to overflow the stack in the passes that recursed into every `Or`.
+#[test]
+fn long_alternations() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..20 {
+        let len = 100 + rng.below(1_900);
+        let mut operands = (0..len).map(|_| random_atoms(&mut rng)).collect::<Vec<_>>();
+        // A char of `ALPHABET` in one of the operands now and then, so that some alternations do not parse.
+        if rng.below(4) == 0 {
+            operands[rng.below(len)].push_str(ALPHABET[rng.below(ALPHABET.len())]);
+        }
+        compile_and_match(&operands.join("|"));
+    }
+}
+
+// Nested repetitions multiply the copies of their operand, which used to make huge programs.
+#[test]
+fn nested_repetitions() {
+    let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+    for _ in 0..200 {
+        let mut pattern = random_atoms(&mut rng);
+        for _ in 0..1 + rng.below(3) {
+            // Bounds up to just above the limit of 1000.
+            let max = rng.below(1_002);
+            let bounds = match rng.below(3) {
+                0 => format!("{{{max}}}"),
+                1 => format!("{{{max},}}"),
+                _ => format!("{{{},{max}}}", rng.below(max + 1)),
+            };
+            pattern = format!("({pattern}){bounds}");
+        }
+
+        match Regex::new(&pattern) {
+            // Every combination of the optional copies can be backtracked to, so only the small programs are matched.
+            Ok(re) if re.instructions().len() <= 40 => compile_and_match(&pattern),
+            Ok(_) => {}
+            Err(SyntaxError::ParseError(
+                ParseError::InvalidRepetition | ParseError::RepetitionTooLarge,
+            )) => {}
+            Err(err) => panic!("{pattern}: {err}"),
+        }
+    }
+}
+
+// A pattern nested thousands of levels deep used to overflow the stack in the recursive passes over the AST.
+#[test]
+fn deep_nesting() {
+    let too_deep = |pattern: &str| matches!(parse(pattern), Err(ParseError::NestingTooDeep));
+    let n = 100_000;
+    assert!(too_deep(&("a".to_string() + &"*".repeat(n))));
+    assert!(too_deep(&("(a".repeat(n) + &")*".repeat(n))));
+    assert!(too_deep(&("(?=".repeat(n) + "a" + &")".repeat(n))));
+    // Groups of a single element add no level.
+    compile_and_match(&("(".repeat(n) + "a" + &")".repeat(n)));
+}
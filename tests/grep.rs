@@ -0,0 +1,74 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Run the grep example on a file with the given lines, and return its output.
+fn grep(args: &[&str], lines: &[&str]) -> String {
+    // Tests run in parallel, so each run gets its own file.
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("vmregex-grep-{}-{run}.txt", process::id()));
+    fs::write(&path, lines.join("\n")).unwrap();
+
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--example", "grep", "--"])
+        .args(args)
+        .arg(&path)
+        .current_dir(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn multiple_patterns() {
+    let lines = ["root:x:0:0", "daemon:x:1:1", "bin:x:2:2", "sys:x:3:3"];
+    assert_eq!(
+        grep(&["-e", "root", "--regexp", "s+y"], &lines),
+        "root:x:0:0\nsys:x:3:3\n"
+    );
+    assert_eq!(grep(&["bin"], &lines), "bin:x:2:2\n");
+}
+
+#[test]
+fn anchored_pattern() {
+    let lines = ["abc", "xabc", "ab", "b"];
+    // `\G` is the start of the line, not of each offset tried.
+    assert_eq!(grep(&[r"\Gab"], &lines), "abc\nab\n");
+    assert_eq!(grep(&["-e", r"\Gb", "-e", "x"], &lines), "xabc\nb\n");
+}
+
+#[test]
+fn line_regexp() {
+    let lines = ["ab", "", "abc", "xab", "abab"];
+    assert_eq!(grep(&["-x", "(ab)+"], &lines), "ab\nabab\n");
+    assert_eq!(
+        grep(&["--line-regexp", "-e", "abc", "-e", "x.*"], &lines),
+        "abc\nxab\n"
+    );
+    assert_eq!(grep(&["-x", "a*"], &lines), "\n");
+    assert_eq!(grep(&["-x", "-o", "a*|ab"], &lines), "ab\n");
+}
+
+#[test]
+fn only_matching() {
+    let lines = ["id=12 port=8080", "no digits", "x9"];
+    assert_eq!(grep(&["-o", "[0-9]+"], &lines), "12\n8080\n9\n");
+    // The matches of several patterns are printed from left to right.
+    assert_eq!(
+        grep(&["--only-matching", "-e", "port", "-e", "[0-9]+"], &lines),
+        "12\nport\n8080\n9\n"
+    );
+    // Empty matches are not printed.
+    assert_eq!(grep(&["-o", "x*"], &lines), "x\n");
+    // An empty match does not hide a non-empty one at the same position, whatever the order of the patterns.
+    assert_eq!(grep(&["-o", "-e", "x*", "-e", "ab"], &["ab"]), "ab\n");
+    assert_eq!(grep(&["-o", "-e", "ab", "-e", "x*"], &["ab"]), "ab\n");
+    assert_eq!(grep(&["-o", "-e", "a", "-e", "ab"], &["ab"]), "ab\n");
+}